@@ -1,6 +1,6 @@
-use std::env;
+use std::{collections::HashMap, env, path::PathBuf, sync::Arc};
 
-use image_rpg::{Handler, Scene, Size};
+use image_rpg::{engine::FileLoader, Handler, Overflow, Scene, Size};
 use rusttype::{Font, Scale};
 use serenity::Client;
 
@@ -11,6 +11,8 @@ async fn main() {
 
     let scene = Scene {
         font,
+        fallback: Vec::new(),
+        fonts: HashMap::new(),
         scale: Scale::uniform(24.0),
         screen: Size {
             xmin: 0,
@@ -30,6 +32,7 @@ async fn main() {
             ymin: 340,
             ymax: 480,
         },
+        overflow: Overflow::Paginate,
     };
 
     let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
@@ -50,6 +53,8 @@ async fn main() {
             script_path: "resources/script.txt".to_owned(),
             guild_id,
             scene,
+            loader: Arc::new(FileLoader::default()),
+            data_dir: PathBuf::new(),
         })
         .application_id(application_id)
         .await
@@ -1,4 +1,6 @@
-use image_rpg::{Engine, Scene, Size};
+use std::collections::HashMap;
+
+use image_rpg::{engine::FileLoader, Engine, Overflow, Scene, Size};
 use rusttype::{Font, Scale};
 
 fn main() {
@@ -7,6 +9,8 @@ fn main() {
 
     let s = Scene {
         font,
+        fallback: Vec::new(),
+        fonts: HashMap::new(),
         scale: Scale::uniform(24.0),
         screen: Size {
             xmin: 0,
@@ -26,9 +30,11 @@ fn main() {
             ymin: 340,
             ymax: 480,
         },
+        overflow: Overflow::Paginate,
     };
 
-    let mut engine = Engine::from_file("resources/script.txt", s).expect("Cannot construct engine");
+    let mut engine = Engine::load("resources/script.txt", s, FileLoader::default())
+        .expect("Cannot construct engine");
     println!("{:#?}", engine.script);
 
     while engine.current().is_some() {
@@ -41,6 +47,6 @@ fn main() {
                 .unwrap(),
             engine.iscript
         ));
-        engine.next(true).unwrap();
+        engine.next(Some(0)).unwrap();
     }
 }
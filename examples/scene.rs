@@ -1,4 +1,10 @@
-use image_rpg::{engine::engine::Attributes, img::load_image, Scene, Size, SpriteDirective};
+use std::{collections::HashMap, sync::Arc};
+
+use image_rpg::{
+    engine::{markup::Run, FileLoader, Frame, FrameContent, FrameSprite},
+    img::read_image,
+    Overflow, Scene, Size,
+};
 use rusttype::{Font, Scale};
 
 fn main() {
@@ -7,6 +13,8 @@ fn main() {
 
     let s = Scene {
         font,
+        fallback: Vec::new(),
+        fonts: HashMap::new(),
         scale: Scale::uniform(24.0),
         screen: Size {
             xmin: 0,
@@ -26,26 +34,36 @@ fn main() {
             ymin: 340,
             ymax: 480,
         },
+        overflow: Overflow::Paginate,
     };
 
-    let image = s.draw_dialogue(
-        Some(&load_image("resources/bgs/bg1.png").unwrap()),
-        &[SpriteDirective {
+    let loader = FileLoader::default();
+    let image = s.draw_frame(&Frame {
+        bg_path: Some("resources/bgs/bg1.png".to_owned()),
+        bg: Some(Arc::new(
+            read_image(&loader, "resources/bgs/bg1.png").unwrap(),
+        )),
+        sprites: vec![FrameSprite {
             name: "x".to_owned(),
-            sprite: None,
-            sprite_path: Some("resources/sprites/Mon1.png".to_owned()),
-            x: Some(0),
-            y: Some(0),
-            show: true,
+            path: "resources/sprites/Mon1.png".to_owned(),
+            x: 0,
+            y: 0,
+            scale: None,
+            image: Arc::new(read_image(&loader, "resources/sprites/Mon1.png").unwrap()),
         }],
-        "Frog",
-        "AAAAAAAAAAAAAAAAAAAAAAAA",
-        &Attributes::default(),
-    );
+        content: FrameContent::Dialogue {
+            speaker: "Frog".to_owned(),
+            text: "AAAAAAAAAAAAAAAAAAAAAAAA".to_owned(),
+            runs: vec![Run::plain("AAAAAAAAAAAAAAAAAAAAAAAA")],
+            text_color: [255, 255, 255, 255],
+            dialogue_color: [0, 0, 0, 127],
+            font: None,
+        },
+    });
     image.save("image_dialogue.png").unwrap();
     let image = s.draw_choice(
-        Some(&load_image("resources/bgs/bg1.png").unwrap()),
-        &("Choice one", "Choice two"),
+        Some(&read_image(&loader, "resources/bgs/bg1.png").unwrap()),
+        &["Choice one", "Choice two"],
     );
     image.save("image_choice.png").unwrap();
 }
@@ -1,8 +1,14 @@
-use std::process::exit;
+use std::{
+    path::{Path, PathBuf},
+    process::exit,
+    sync::Arc,
+};
 
-use image_rpg::{Config, Handler, Scene, Size};
+use image_rpg::{
+    engine::{FileLoader, Package, ScriptLoader},
+    Config, Handler, Scene,
+};
 use log::{debug, error, info};
-use rusttype::{Font, Scale};
 use serenity::Client;
 use songbird::SerenityInit;
 
@@ -11,43 +17,33 @@ async fn main() {
     env_logger::init();
 
     info!("{}", "Discord VN engine 1.0.0");
-    debug!("{}", "Loading config file resources/config.conf");
+    // Every path of the game, including those in scripts, is relative to its root, which is
+    // either a directory or a package
+    let root = std::env::args().nth(1).unwrap_or_else(|| ".".to_string());
+    let (loader, data_dir): (Arc<dyn ScriptLoader>, _) = if Path::new(&root).is_file() {
+        let package = Package::open(&root).unwrap_or_else(|e| {
+            error!("{}", e);
+            exit(1);
+        });
+        // Saves and renders are written next to a package
+        let data_dir = Path::new(&root).parent().map(Path::to_path_buf);
+        (Arc::new(package), data_dir.unwrap_or_default())
+    } else {
+        (Arc::new(FileLoader::new(&root)), PathBuf::from(&root))
+    };
+    debug!("Loading config file resources/config.conf in {}", root);
 
-    let config = match Config::from_file("resources/config.conf") {
+    let config = match Config::load("resources/config.conf", loader.as_ref()) {
         Ok(conf) => conf,
         Err(err) => {
             error!("{}", err);
             exit(1);
         }
     };
-
-    let font_data = include_bytes!("../../resources/fonts/calibri-regular.ttf");
-    let font = Font::try_from_bytes(font_data as &[u8]).unwrap_or_else(|| {
-        error!("{}", "Error constructing Font");
+    let scene = Scene::from_config(&config, loader.as_ref()).unwrap_or_else(|e| {
+        error!("{}", e);
         exit(1);
     });
-    let scene = Scene {
-        font,
-        scale: Scale::uniform(24.0),
-        screen: Size {
-            xmin: 0,
-            ymin: 0,
-            ymax: 480,
-            xmax: 640,
-        },
-        sprite: Size {
-            xmin: 0,
-            xmax: 0,
-            ymin: 0,
-            ymax: 0,
-        },
-        text: Size {
-            xmin: 20,
-            xmax: 620,
-            ymin: 340,
-            ymax: 470,
-        },
-    };
 
     let discord = config.fields.get("Discord").unwrap_or_else(|| {
         error!("{}", "Discord field not found in config file");
@@ -101,6 +97,8 @@ async fn main() {
                 .to_owned(),
             guild_id,
             scene,
+            loader,
+            data_dir,
         })
         .application_id(application_id)
         .register_songbird()
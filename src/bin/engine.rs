@@ -1,15 +1,79 @@
 use image_rpg::{
-    engine::{ScriptContext, ScriptDirective},
-    Config, Engine, Scene, Size,
+    engine::{
+        graph::StoryGraph, lint, package, FileLoader, Package, ReadLog, SaveData, ScriptContext,
+        ScriptDirective, ScriptLoader,
+    },
+    Config, Engine, Scene,
 };
 use log::{debug, error, info, warn};
-use rusttype::{Font, Scale};
 use std::{
     fs,
     io::{self, Write},
+    path::{Path, PathBuf},
     process::exit,
+    sync::Arc,
 };
 
+/// Where a story is saved when quitting at a choice, relative to the game's root
+const SAVE_PATH: &str = "resources/save.json";
+/// Where dialogues read in every run are kept
+const READ_PATH: &str = "resources/read.json";
+
+/// Every script file under dir, relative to root
+fn script_files(root: &Path, dir: &Path, files: &mut Vec<String>) {
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            script_files(root, &path, files);
+        } else if path.extension().is_some_and(|ext| ext == "txt") {
+            let path = path.strip_prefix(root).unwrap_or(&path);
+            files.push(path.to_string_lossy().into_owned());
+        }
+    }
+}
+
+/// Every script next to the story's first script at script_path, in the game at root
+/// or in its package
+fn story_files(script_path: &str, root: &Path, package: Option<&Package>) -> Vec<String> {
+    let dir = Path::new(script_path)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    let mut files = Vec::new();
+    match package {
+        Some(package) => files.extend(
+            package
+                .manifest()
+                .files
+                .iter()
+                .filter(|file| Path::new(file).starts_with(dir) && file.ends_with(".txt"))
+                .cloned(),
+        ),
+        None => script_files(root, &root.join(dir), &mut files),
+    }
+    files
+}
+
+/// Log every problem in the story, returning whether there are none
+fn lint_story(script_path: &str, loader: &dyn ScriptLoader, story_files: &[String]) -> bool {
+    info!("Linting {} and every script it jumps to", script_path);
+    let lints = lint::lint(script_path, loader, story_files);
+    for lint in &lints {
+        warn!("{}", lint);
+    }
+    if !lints.is_empty() {
+        error!("Found {} problems", lints.len());
+        return false;
+    }
+    info!("No problems found");
+    true
+}
+
+fn save_read(engine: &Engine, read_path: &str) {
+    if let Err(e) = engine.read_log().write_to_file(read_path) {
+        warn!("Cannot save read dialogues: {}", e);
+    }
+}
+
 fn main() {
     if std::env::var("RUST_LOG").is_err() {
         std::env::set_var("RUST_LOG", "debug");
@@ -18,35 +82,49 @@ fn main() {
 
     info!("Discord VN scripting engine v1.0.0");
 
-    let font_data = include_bytes!("../../resources/fonts/calibri-regular.ttf");
-    debug!("Loading font");
-    let font = Font::try_from_bytes(font_data as &[u8]).expect("Error constructing Font");
-    debug!("Font loaded");
-
-    let scene = Scene {
-        font,
-        scale: Scale::uniform(24.0),
-        screen: Size {
-            xmin: 0,
-            ymin: 0,
-            ymax: 480,
-            xmax: 640,
-        },
-        sprite: Size {
-            xmin: 0,
-            ymin: 0,
-            ymax: 0,
-            xmax: 0,
-        },
-        text: Size {
-            xmin: 20,
-            xmax: 620,
-            ymin: 340,
-            ymax: 470,
-        },
+    // Flags may appear anywhere, the other arguments are positional
+    let (flags, args): (Vec<_>, Vec<_>) = std::env::args().partition(|arg| arg.starts_with("--"));
+    let skip_read = flags.iter().any(|flag| flag == "--skip-read");
+    let root = flags
+        .iter()
+        .find_map(|flag| flag.strip_prefix("--root="))
+        .unwrap_or(".");
+    if let Some(flag) = flags
+        .iter()
+        .find(|flag| *flag != "--skip-read" && !flag.starts_with("--root="))
+    {
+        error!("Unknown flag {}", flag);
+        exit(1);
+    }
+    // Every path of the game, including those in scripts, is relative to its root, which is
+    // either a directory or a package
+    let package = Path::new(root).is_file().then(|| {
+        Arc::new(Package::open(root).unwrap_or_else(|e| {
+            error!("{}", e);
+            exit(1);
+        }))
+    });
+    let loader: Arc<dyn ScriptLoader> = match &package {
+        Some(package) => package.clone(),
+        None => Arc::new(FileLoader::new(root)),
+    };
+    // Renders and saves are written next to a package
+    let data_dir = match &package {
+        Some(_) => Path::new(root)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+        None => PathBuf::from(root),
     };
+    let in_data = |path: &str| data_dir.join(path).to_string_lossy().into_owned();
+    let (save_path, read_path) = (in_data(SAVE_PATH), in_data(READ_PATH));
 
-    let config = Config::from_file("resources/config.conf").unwrap_or_else(|e| {
+    let config = Config::load("resources/config.conf", &loader).unwrap_or_else(|e| {
+        error!("{}", e);
+        exit(1);
+    });
+    debug!("Loading scene");
+    let scene = Scene::from_config(&config, loader.as_ref()).unwrap_or_else(|e| {
         error!("{}", e);
         exit(1);
     });
@@ -64,29 +142,105 @@ fn main() {
             exit(1);
         });
     debug!("Current working directory: {:?}", std::env::current_dir());
+    debug!("Game root: {:?}", root);
+
+    let mode = args.get(1).cloned();
+    if mode.as_deref() == Some("graph") {
+        let graph = StoryGraph::build(script_path, loader.as_ref());
+        match args.get(2).map(String::as_str) {
+            Some("dot") | None => print!("{}", graph.to_dot()),
+            Some("json") => println!("{}", graph.to_json()),
+            Some(format) => {
+                error!("Unknown graph format {}, expected dot or json", format);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    if mode.as_deref() == Some("lint") {
+        let story_files = story_files(script_path, Path::new(root), package.as_deref());
+        if !lint_story(script_path, loader.as_ref(), &story_files) {
+            exit(1);
+        }
+        return;
+    }
+
+    if mode.as_deref() == Some("pack") {
+        if package.is_some() {
+            error!("{} is already a package", root);
+            exit(1);
+        }
+        let path = args.get(2).map(String::as_str).unwrap_or("game.zip");
+        let clean = package::pack(Path::new(root), Path::new(path)).and_then(|manifest| {
+            info!("Packed {} files into {}", manifest.files.len(), path);
+            // Lint the story as read from the package so missing files are caught now
+            let package = Package::open(path)?;
+            let story_files = story_files(script_path, Path::new(root), Some(&package));
+            Ok(lint_story(script_path, &package, &story_files))
+        });
+        if let Err(e) = &clean {
+            error!("{}", e);
+        }
+        // Packages with problems are removed so they are not shipped by mistake
+        if !clean.unwrap_or(false) {
+            if fs::remove_file(path).is_ok() {
+                info!("Removed {}", path);
+            }
+            exit(1);
+        }
+        return;
+    }
     debug!("Engine initializing, searching for {}", script_path);
-    match Engine::from_file(script_path.as_str(), scene) {
+    let engine = if mode.as_deref() == Some("resume") {
+        let save_path = args.get(2).cloned().unwrap_or_else(|| save_path.clone());
+        debug!("Resuming from {}", save_path);
+        SaveData::from_file(&save_path)
+            .and_then(|save| Engine::restore_with(save, scene, loader.clone()))
+            .map_err(|e| e.to_string())
+    } else {
+        Engine::load(script_path, scene, loader.clone()).map_err(|e| e.to_string())
+    };
+    match engine {
         Ok(mut engine) => {
             debug!("Engine initialized. Rendering...");
             info!("It should be noted that if there are conditional jumps in the script, you will be prompted.");
             debug!("Removing previous render files");
-            for file in fs::read_dir("resources/render").unwrap().flatten() {
+            let render_dir = data_dir.join("resources/render");
+            fs::create_dir_all(&render_dir).unwrap();
+            for file in fs::read_dir(&render_dir).unwrap().flatten() {
                 if file.file_type().unwrap().is_file() {
                     debug!("Removing file {}", file.file_name().to_str().unwrap());
                     fs::remove_file(file.path()).unwrap();
                 }
             }
+            match ReadLog::load(&read_path) {
+                Ok(read) => engine.set_read_log(read),
+                Err(e) => warn!("Cannot load read dialogues, starting afresh: {}", e),
+            }
             if std::env::var("NO_CACHE").is_err() {
-                engine.enable_cache();
-                warn!("Cache is enabled. Unexpected rendering may occur. Remove resources/.cache to forcefully render every frame if unexpected results arise.");
+                engine.enable_cache(render_dir.join(".cache"));
+                warn!("Cache is enabled. Unexpected rendering may occur. Remove resources/render/.cache to forcefully render every frame if unexpected results arise.");
             }
 
             while let Some(ctx) = engine.current() {
-                let mut choice = false;
+                let mut choice = None;
+                let mut back = false;
                 match ctx {
+                    ScriptContext::Dialogue(_) if skip_read && engine.is_read() => {
+                        debug!("Skipping read dialogues");
+                        if let Err(e) = engine.skip_read() {
+                            error!("Cannot continue loading script: {}", e);
+                            exit(1);
+                        }
+                        continue;
+                    }
                     ScriptContext::Dialogue(dialogue) => {
+                        let (page, pages) = engine.page().unwrap_or((0, 1));
                         debug!(
-                            "Rendering dialogue: \"{}\"",
+                            "Rendering page {} of {} of dialogue: \"{}\"",
+                            page + 1,
+                            pages,
                             dialogue.dialogues.iter().fold(String::new(), |a, b| a + b)
                         );
                         rendered += 1;
@@ -96,37 +250,66 @@ fn main() {
                             debug!("Loading background {}", loadbg.bg_path);
                         }
                         ScriptDirective::Jump(jump) => match &jump.choices {
-                            Some((a, b)) => {
+                            Some(choices) => {
                                 rendered += 1;
+                                let width = choices
+                                    .iter()
+                                    .map(|choice| choice.text.len())
+                                    .max()
+                                    .unwrap_or(0)
+                                    * 2
+                                    + 5;
                                 let mut buf = String::new();
                                 loop {
-                                    info!("A conditional jump was found, choose 1 or 2.",);
+                                    info!(
+                                        "A conditional jump was found, choose 1 to {}, b to go back or s to save and quit.",
+                                        choices.len()
+                                    );
+                                    println!("+{nothing:-<width$}+", nothing = "", width = width);
+                                    for (i, choice) in choices.iter().enumerate() {
+                                        println!(
+                                            "| [{i}] {text:<xwidth$}|",
+                                            i = i + 1,
+                                            text = choice.text,
+                                            xwidth = width - 5,
+                                        );
+                                    }
                                     print!(
-                                        "+{nothing:-<width$}+\n\
-                                        | [1] {a:<xwidth$}|\n\
-                                        | [2] {b:<xwidth$}|\n\
-                                        +{nothing:-<width$}+\n\
-                                        (1 or 2) > ",
+                                        "+{nothing:-<width$}+\n(1 to {n}) > ",
                                         nothing = "",
-                                        width = a.len().max(b.len()) * 2,
-                                        xwidth = a.len().max(b.len()) * 2 - 5,
+                                        width = width,
+                                        n = choices.len(),
                                     );
                                     io::stdout().flush().unwrap();
                                     io::stdin().read_line(&mut buf).unwrap();
-                                    match buf.trim().parse::<u64>() {
-                                        Ok(num) => match num {
-                                            1 => {
-                                                choice = true;
-                                                break;
-                                            }
-                                            2 => {
-                                                choice = false;
-                                                break;
+                                    if buf.trim() == "b" && engine.has_history() {
+                                        back = true;
+                                        break;
+                                    }
+                                    if buf.trim() == "s" {
+                                        save_read(&engine, &read_path);
+                                        match engine.save().write_to_file(&save_path) {
+                                            Ok(()) => {
+                                                info!(
+                                                    "Saved to {}. Run engine resume to continue.",
+                                                    save_path
+                                                );
+                                                exit(0);
                                             }
-                                            _ => error!(
-                                                "The choice must be either 1 or 2. Reprompting."
-                                            ),
-                                        },
+                                            Err(e) => error!("{}. Reprompting.", e),
+                                        }
+                                        buf.clear();
+                                        continue;
+                                    }
+                                    match buf.trim().parse::<usize>() {
+                                        Ok(num) if (1..=choices.len()).contains(&num) => {
+                                            choice = Some(num - 1);
+                                            break;
+                                        }
+                                        Ok(_) => error!(
+                                            "The choice must be between 1 and {}. Reprompting.",
+                                            choices.len()
+                                        ),
                                         Err(_) => {
                                             error!("The choice number must be an integer. Reprompting.");
                                         }
@@ -134,7 +317,11 @@ fn main() {
                                     buf.clear();
                                 }
                             }
-                            None => debug!("Jumping to {}", jump.endpoint.script_path),
+                            None => {
+                                if let Some(endpoint) = &jump.endpoint {
+                                    debug!("Jumping to {}", endpoint)
+                                }
+                            }
                         },
                         ScriptDirective::Sprite(sprite) => {
                             if let Some(sprite_path) = &sprite.sprite_path {
@@ -149,14 +336,46 @@ fn main() {
                         ScriptDirective::Custom(custom) => {
                             debug!("Ignoring custom directive {:#?}", custom)
                         }
+                        ScriptDirective::Label(label) => {
+                            debug!("Passing label {}", label.name)
+                        }
+                        ScriptDirective::Set(set) => {
+                            debug!("Setting variable {:?}", set);
+                        }
+                        ScriptDirective::If(_)
+                        | ScriptDirective::Elif(_)
+                        | ScriptDirective::Else
+                        | ScriptDirective::EndIf => {}
                     },
                 };
-                engine.cache_render_to(&format!("resources/render/render_{}.png", rendered));
+                if back {
+                    engine.back();
+                    continue;
+                }
+                engine.cache_render_to(
+                    &render_dir
+                        .join(format!("render_{}.png", rendered))
+                        .to_string_lossy(),
+                );
                 if let Err(e) = engine.next(choice) {
                     error!("Cannot continue loading script: {}", e);
                     exit(1);
                 }
             }
+
+            save_read(&engine, &read_path);
+            let choices = engine
+                .backlog()
+                .filter_map(|entry| entry.picked().map(|picked| (entry, picked)))
+                .collect::<Vec<_>>();
+            info!(
+                "Finished after {} dialogues and {} choices",
+                engine.backlog().count() - choices.len(),
+                choices.len()
+            );
+            for (entry, picked) in choices {
+                info!("Picked \"{}\" in {}", picked, entry.script);
+            }
         }
         Err(e) => {
             error!("{}", e);
@@ -114,7 +114,7 @@ fn view(app: &App, model: &Model, frame: Frame) {
             let (width, height) = sprite.get_scaled();
             draw.scissor(Rect::from_x_y_w_h(140., -100., 640., 480.))
                 .texture(&texture)
-                .w_h(width as f32 * sprite.scale, height as f32 * sprite.scale)
+                .w_h(width * sprite.scale, height * sprite.scale)
                 .xy(sprite.position + pt2(140., 100.) - pt2(320., 240.));
             draw.ellipse()
                 .resolution(16.)
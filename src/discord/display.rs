@@ -1,7 +1,10 @@
-use std::sync::Arc;
+use std::{fs, sync::Arc};
 
+use log::{error, warn};
 use serenity::{
-    builder::{CreateComponents, CreateInteractionResponse, EditInteractionResponse},
+    builder::{
+        CreateActionRow, CreateComponents, CreateInteractionResponse, EditInteractionResponse,
+    },
     client::{bridge::gateway::ShardMessenger, Context},
     futures::StreamExt,
     http::Http,
@@ -15,60 +18,183 @@ use serenity::{
 };
 
 use crate::{
-    engine::{ParseError, ScriptContext, ScriptDirective},
+    engine::{ReadLog, SaveData, SaveError, ScriptLoader},
     Config, Engine, Scene,
 };
 
-use super::voice::play_url;
+use super::voice::{play, PlayHandler};
 
-struct PlayInfo(u64, u64, String, f32);
 pub struct Begin {
     config: Config,
     engine: Engine,
+    plays: PlayHandler,
+    /// Reads the files of the game, such as the songs played
+    loader: Arc<dyn ScriptLoader>,
+    /// Where progress is saved after every step
+    save_file: String,
+    /// Where read dialogues are kept across every playthrough
+    read_file: String,
+    /// Where frames are rendered before being sent
+    render_file: String,
 }
 
 impl Begin {
-    pub fn new(config_file: &str, script_file: &str, scene: Scene) -> Result<Self, ParseError> {
+    /// Start the story at script_file, reading the files of the game through loader and
+    /// rendering frames to render_file
+    pub fn new(
+        config_file: &str,
+        script_file: &str,
+        save_file: &str,
+        read_file: &str,
+        render_file: &str,
+        scene: Scene,
+        loader: Arc<dyn ScriptLoader>,
+    ) -> Result<Self, SaveError> {
+        Self::with_engine(
+            config_file,
+            Engine::load(script_file, scene, loader.clone())?,
+            save_file,
+            read_file,
+            render_file,
+            loader,
+        )
+    }
+
+    /// Resume the story saved in save_file
+    pub fn resume(
+        config_file: &str,
+        save_file: &str,
+        read_file: &str,
+        render_file: &str,
+        scene: Scene,
+        loader: Arc<dyn ScriptLoader>,
+    ) -> Result<Self, SaveError> {
+        Self::with_engine(
+            config_file,
+            Engine::restore_with(SaveData::from_file(save_file)?, scene, loader.clone())?,
+            save_file,
+            read_file,
+            render_file,
+            loader,
+        )
+    }
+
+    fn with_engine(
+        config_file: &str,
+        mut engine: Engine,
+        save_file: &str,
+        read_file: &str,
+        render_file: &str,
+        loader: Arc<dyn ScriptLoader>,
+    ) -> Result<Self, SaveError> {
+        let plays = PlayHandler::default();
+        engine.register_custom("play", plays.clone())?;
+        engine.set_read_log(ReadLog::load(read_file)?);
         Ok(Self {
-            config: Config::from_file(config_file)?,
-            engine: Engine::from_file(script_file, scene)?,
+            config: Config::load(config_file, loader.as_ref())?,
+            engine,
+            plays,
+            loader,
+            save_file: save_file.to_string(),
+            read_file: read_file.to_string(),
+            render_file: render_file.to_string(),
         })
     }
 
+    /// Play the songs of every play directive passed since the last call
+    async fn play_queued(&self, context: &Context) {
+        let plays = std::mem::take(&mut *self.plays.queue.lock().unwrap());
+        for song in plays {
+            if let Err(e) = play(
+                context,
+                self.loader.as_ref(),
+                song.guild_id,
+                song.channel_id,
+                &song.source,
+                song.volume,
+            )
+            .await
+            {
+                warn!("Cannot play {}: {}", song.source, e);
+            }
+        }
+    }
+
+    fn autosave(&self) {
+        if let Err(e) = self.engine.save().write_to_file(&self.save_file) {
+            warn!("Cannot save progress: {}", e);
+        }
+        if let Err(e) = self.engine.read_log().write_to_file(&self.read_file) {
+            warn!("Cannot save read dialogues: {}", e);
+        }
+    }
+
     pub fn delegate_component<'a>(
         &self,
         component: &'a mut CreateComponents,
     ) -> &'a mut CreateComponents {
-        component.create_action_row(|row| match self.engine.current().unwrap() {
-            ScriptContext::Dialogue(_) => row.create_button(|button| {
-                button
-                    .label("➡️")
-                    .custom_id("right_page_select")
-                    .style(ButtonStyle::Primary)
+        let back = |row: &mut CreateActionRow| {
+            if self.engine.has_history() {
+                row.create_button(|button| {
+                    button
+                        .label("⬅️")
+                        .custom_id("left_page_select")
+                        .style(ButtonStyle::Secondary)
+                });
+            }
+        };
+        let frame = self
+            .engine
+            .frame()
+            .expect("Unexpected directive found during discord rendering");
+        match frame.choices() {
+            None => component.create_action_row(|row| {
+                back(row);
+                row.create_button(|button| {
+                    button
+                        .label("➡️")
+                        .custom_id("right_page_select")
+                        .style(ButtonStyle::Primary)
+                })
+                .create_button(|button| {
+                    button
+                        .label("⏩")
+                        .custom_id("skip_select")
+                        .style(ButtonStyle::Secondary)
+                })
             }),
-            ScriptContext::Directive(directive) => {
-                if let ScriptDirective::Jump(jump) = directive {
-                    if let Some((choice_a, choice_b)) = &jump.choices {
-                        row.create_button(|button| {
-                            button
-                                .label(choice_a)
-                                .custom_id("first_choice_select")
-                                .style(ButtonStyle::Primary)
-                        })
-                        .create_button(|button| {
-                            button
-                                .label(choice_b)
-                                .custom_id("second_choice_select")
-                                .style(ButtonStyle::Primary)
-                        })
-                    } else {
+            Some(choices) => {
+                // Discord allows at most 5 buttons per action row
+                for (irow, row_choices) in choices.chunks(5).enumerate() {
+                    component.create_action_row(|row| {
+                        for (i, choice) in row_choices.iter().enumerate() {
+                            row.create_button(|button| {
+                                button
+                                    .label(choice)
+                                    .custom_id(format!("choice_select_{}", irow * 5 + i))
+                                    .style(ButtonStyle::Primary)
+                            });
+                        }
+                        row
+                    });
+                }
+                // Jumps have at most MAX_CHOICES choices, leaving a row for the back button
+                if self.engine.has_history() {
+                    component.create_action_row(|row| {
+                        back(row);
                         row
-                    }
-                } else {
-                    row
+                    });
                 }
+                component
             }
-        })
+        }
+    }
+
+    fn description(&self) -> String {
+        self.engine
+            .frame()
+            .expect("Unexpected directive found during discord rendering")
+            .describe()
     }
 
     fn delegate_interaction_response<'a>(
@@ -80,34 +206,11 @@ impl Begin {
             data.components(|components| self.delegate_component(components))
                 .create_embed(|embed| {
                     embed
-                        .title(&format!(
+                        .title(format!(
                             "You are currently playing {}",
                             self.config.fields.get("Game").unwrap().get("name").unwrap()
                         ))
-                        .description(&match self.engine.current().unwrap() {
-                            ScriptContext::Dialogue(dialogue) => {
-                                if dialogue.character_name.trim().is_empty() {
-                                    dialogue.dialogues.join(" ")
-                                } else {
-                                    format!(
-                                        "{}: {}",
-                                        dialogue.character_name,
-                                        dialogue.dialogues.join(" ")
-                                    )
-                                }
-                            }
-                            ScriptContext::Directive(directive) => {
-                                if let ScriptDirective::Jump(jump) = directive {
-                                    format!(
-                                        "You are presented with two choices:\n[1] {}\n[2] {}",
-                                        jump.choices.as_ref().unwrap().0,
-                                        jump.choices.as_ref().unwrap().1
-                                    )
-                                } else {
-                                    panic!("Unexpected directive found during discord rendering")
-                                }
-                            }
-                        })
+                        .description(self.description())
                         .image(display_link)
                 })
         })
@@ -122,34 +225,11 @@ impl Begin {
             .components(|components| self.delegate_component(components))
             .create_embed(|embed| {
                 embed
-                    .title(&format!(
+                    .title(format!(
                         "You are currently playing {}",
                         self.config.fields.get("Game").unwrap().get("name").unwrap()
                     ))
-                    .description(&match self.engine.current().unwrap() {
-                        ScriptContext::Dialogue(dialogue) => {
-                            if dialogue.character_name.trim().is_empty() {
-                                dialogue.dialogues.join(" ")
-                            } else {
-                                format!(
-                                    "{}: {}",
-                                    dialogue.character_name,
-                                    dialogue.dialogues.join(" ")
-                                )
-                            }
-                        }
-                        ScriptContext::Directive(directive) => {
-                            if let ScriptDirective::Jump(jump) = directive {
-                                format!(
-                                    "You are presented with two choices:\n[1] {}\n[2] {}",
-                                    jump.choices.as_ref().unwrap().0,
-                                    jump.choices.as_ref().unwrap().1
-                                )
-                            } else {
-                                panic!("Unexpected directive found during discord rendering")
-                            }
-                        }
-                    })
+                    .description(self.description())
                     .image(display_link)
             })
     }
@@ -172,57 +252,19 @@ impl Begin {
                 .expect("image_channel must be an integer"),
         );
 
-        let renderable = |ctx: &ScriptContext| match ctx {
-            ScriptContext::Dialogue(_) => true,
-            ScriptContext::Directive(directive) => match directive {
-                ScriptDirective::Jump(jump) if jump.choices.is_some() => true,
-                ScriptDirective::Custom(custom) if custom.name == "play" => true,
-                _ => false,
-            },
-        };
-        let mut play_info: Option<PlayInfo> = None;
-
-        while let Some(ctx) = self.engine.next_until(renderable).unwrap() {
-            if let ScriptContext::Directive(ScriptDirective::Custom(custom)) = ctx {
-                let guild_id = custom
-                    .args
-                    .get(0)
-                    .expect("Guild id expected for play")
-                    .parse()
-                    .expect("Guild id must be an integer");
-                let channel_id = custom
-                    .args
-                    .get(1)
-                    .expect("Channel id expected for play")
-                    .parse()
-                    .expect("Channel id must be an integer");
-
-                play_info = Some(PlayInfo(
-                    guild_id,
-                    channel_id,
-                    custom.args.get(2).expect("URL not provided").to_string(),
-                    custom
-                        .args
-                        .get(3)
-                        .map(|v| {
-                            v.parse::<f32>()
-                                .expect("Volume must be a valid float number")
-                        })
-                        .unwrap_or(1.),
-                ));
-                self.engine.next(false).unwrap();
-            } else {
-                break;
-            }
+        if let Err(e) = self.engine.next_until_renderable() {
+            error!("Cannot begin the story: {}", e);
+            return Ok(());
         }
         if self.engine.current().is_none() {
             return Ok(());
         }
 
-        self.engine.render_to("resources/tmp.png");
+        self.engine.render_to(&self.render_file);
+        self.autosave();
 
         let message = temp_channel
-            .send_files(http, vec!["resources/tmp.png"], |m| m)
+            .send_files(http, vec![self.render_file.as_str()], |m| m)
             .await
             .expect("Cannot send file");
 
@@ -233,11 +275,7 @@ impl Begin {
             .await
             .expect("Unable to create interaction");
 
-        if let Some(play_info) = play_info.take() {
-            play_url(context, play_info.0, play_info.1, &play_info.2, play_info.3)
-                .await
-                .expect("Cannot play URL");
-        }
+        self.play_queued(context).await;
         let mut collector = interaction
             .get_interaction_response(http)
             .await?
@@ -246,49 +284,40 @@ impl Begin {
 
         //let next_image;
         while let Some(mci) = collector.next().await {
-            let choice = match mci.data.custom_id.as_str() {
-                "right_page_select" => false,
-                "first_choice_select" => true,
-                "second_choice_select" => false,
-                id => panic!("Cannot handle interaction custom_id {}", id),
+            let advanced = match mci.data.custom_id.as_str() {
+                "left_page_select" => {
+                    self.engine.back();
+                    Ok(None)
+                }
+                "skip_select" => self.engine.skip_read(),
+                id => {
+                    let choice = match id {
+                        "right_page_select" => None,
+                        id => Some(
+                            id.strip_prefix("choice_select_")
+                                .and_then(|i| i.parse::<usize>().ok())
+                                .unwrap_or_else(|| {
+                                    panic!("Cannot handle interaction custom_id {}", id)
+                                }),
+                        ),
+                    };
+                    self.engine.next(choice)
+                }
             };
-            self.engine.next(choice).unwrap();
-            if let Some(_ctx) = self.engine.next_until_renderable().unwrap() {
-                while let Some(ctx) = self.engine.next_until(renderable).unwrap() {
-                    if let ScriptContext::Directive(ScriptDirective::Custom(custom)) = ctx {
-                        let guild_id = custom
-                            .args
-                            .get(0)
-                            .expect("Guild id expected for play")
-                            .parse()
-                            .expect("Guild id must be an integer");
-                        let channel_id = custom
-                            .args
-                            .get(1)
-                            .expect("Channel id expected for play")
-                            .parse()
-                            .expect("Channel id must be an integer");
-
-                        play_info = Some(PlayInfo(
-                            guild_id,
-                            channel_id,
-                            custom.args.get(2).expect("URL not provided").to_string(),
-                            custom
-                                .args
-                                .get(3)
-                                .map(|v| {
-                                    v.parse::<f32>()
-                                        .expect("Volume must be a valid float number")
-                                })
-                                .unwrap_or(1.),
-                        ));
-                        self.engine.next(false).unwrap();
-                    } else {
-                        break;
-                    }
+            if let Err(e) = advanced
+                .map(|_| ())
+                .and_then(|_| self.engine.next_until_renderable().map(|_| ()))
+            {
+                warn!("Cannot continue the story: {}", e);
+                // Show the last frame again as the story cannot go past it
+                if self.engine.frame().is_none() {
+                    self.engine.back();
                 }
+            }
+            if self.engine.current().is_some() {
                 //println!("{:#?}", self.engine.current());
-                self.engine.render_to("resources/tmp.png");
+                self.engine.render_to(&self.render_file);
+                self.autosave();
 
                 mci.create_interaction_response(http, |ir| {
                     ir.kind(InteractionResponseType::DeferredUpdateMessage)
@@ -297,7 +326,7 @@ impl Begin {
                 .unwrap();
 
                 let message = temp_channel
-                    .send_files(http, vec!["resources/tmp.png"], |m| m)
+                    .send_files(http, vec![self.render_file.as_str()], |m| m)
                     .await
                     .expect("Cannot send file");
                 mci.edit_original_interaction_response(http, |ir| {
@@ -306,18 +335,16 @@ impl Begin {
                 .await
                 .expect("Cannot update interaction");
 
-                if let Some(play_info) = play_info.take() {
-                    play_url(context, play_info.0, play_info.1, &play_info.2, play_info.3)
-                        .await
-                        .expect("Cannot play URL");
-                }
+                self.play_queued(context).await;
             } else {
+                // The story is over, the next begin or resume starts from the beginning
+                let _ = fs::remove_file(&self.save_file);
                 mci.create_interaction_response(http, |ir| {
                     ir.interaction_response_data(|ird| {
                         ird.create_embed(|embed| {
                             embed
                                 .title("Thank you for using Gary's VN engine!")
-                                .description(&format!(
+                                .description(format!(
                                     "You just finished playing {}",
                                     self.config
                                         .fields
@@ -2,8 +2,8 @@ use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum PlayError {
-    #[error("Invalid URL {0}")]
-    InvalidURL(String),
+    #[error("Cannot read {0}: {1}")]
+    IoError(String, std::io::Error),
     #[error("Songbird unregistered")]
     Unregistered,
     #[error("Input error {0}")]
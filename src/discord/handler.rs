@@ -1,12 +1,18 @@
-use std::process::exit;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::exit,
+    sync::Arc,
+};
 
+use log::{error, warn};
 use serenity::{
     async_trait,
     client::{Context, EventHandler},
     model::{gateway::Ready, id::GuildId, interactions::Interaction},
 };
 
-use crate::Scene;
+use crate::{engine::ScriptLoader, Scene};
 
 use super::display::Begin;
 
@@ -15,27 +21,62 @@ pub struct Handler {
     pub guild_id: u64,
     pub script_path: String,
     pub scene: Scene,
+    /// Reads the files of the game, relative to its root
+    pub loader: Arc<dyn ScriptLoader>,
+    /// Where saves and renders are written
+    pub data_dir: PathBuf,
 }
 
 #[async_trait]
 impl EventHandler for Handler {
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
         if let Interaction::ApplicationCommand(command) = interaction {
-            match command.data.name.as_str() {
-                "begin" => Begin::new(
+            let in_data = |path: String| self.data_dir.join(path).to_string_lossy().into_owned();
+            let save_file = in_data(format!("resources/saves/{}.json", command.user.id));
+            let read_file = in_data(format!("resources/saves/{}.read.json", command.user.id));
+            // Sessions render concurrently, so each user is rendered to their own file
+            let render_file = in_data(format!("resources/saves/{}.png", command.user.id));
+            if let Some(dir) = Path::new(&render_file).parent() {
+                if let Err(e) = fs::create_dir_all(dir) {
+                    warn!("Cannot create {}: {}", dir.display(), e);
+                }
+            }
+            let resumed = match command.data.name.as_str() {
+                "begin" => None,
+                // Without a usable save, resuming starts a new story
+                "resume" if Path::new(&save_file).is_file() => Begin::resume(
+                    &self.config_path,
+                    &save_file,
+                    &read_file,
+                    &render_file,
+                    self.scene.clone(),
+                    self.loader.clone(),
+                )
+                .map_err(|e| warn!("Cannot resume {}: {}", save_file, e))
+                .ok(),
+                "resume" => None,
+                _ => panic!("Unable to handle command!"),
+            };
+            let mut begin = match resumed {
+                Some(begin) => begin,
+                None => Begin::new(
                     self.config_path.as_str(),
                     self.script_path.as_str(),
+                    &save_file,
+                    &read_file,
+                    &render_file,
                     self.scene.clone(),
+                    self.loader.clone(),
                 )
                 .unwrap_or_else(|e| {
-                    eprintln!("{}", e);
+                    error!("{}", e);
                     exit(1);
-                })
+                }),
+            };
+            begin
                 .handle_interaction(&ctx.http, &ctx, command, &ctx.shard)
                 .await
-                .expect("Cannot run begin command"),
-                _ => panic!("Unable to handle command!"),
-            }
+                .expect("Cannot run begin command")
         }
     }
 
@@ -44,7 +85,13 @@ impl EventHandler for Handler {
 
         let guild = GuildId(self.guild_id);
         let guild_command = GuildId::set_application_commands(&guild, &ctx.http, |commands| {
-            commands.create_application_command(|command| command.name("begin").description(":>"))
+            commands
+                .create_application_command(|command| command.name("begin").description(":>"))
+                .create_application_command(|command| {
+                    command
+                        .name("resume")
+                        .description("Continue where you last left off")
+                })
         })
         .await
         .expect("Unable to add guild commands");
@@ -1,18 +1,84 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
 use serenity::client::Context;
 use songbird::input::restartable::Restartable;
 
+use crate::engine::{CustomArgs, CustomHandler, ScriptLoader};
+
 use super::errors::PlayError;
 
-pub async fn play_url(
+/// Song of `@custom(play(guild, channel, source, volume))`
+pub struct PlayInfo {
+    pub guild_id: u64,
+    pub channel_id: u64,
+    /// URL of the song, or the path of its file in the game
+    pub source: String,
+    pub volume: f32,
+}
+
+/// Queues the songs of play directives until the frame following them is sent
+#[derive(Clone, Default)]
+pub struct PlayHandler {
+    pub queue: Arc<Mutex<Vec<PlayInfo>>>,
+}
+
+impl CustomHandler for PlayHandler {
+    type Args = PlayInfo;
+
+    fn parse(&self, args: CustomArgs) -> Result<PlayInfo, String> {
+        args.at_most(4)?;
+        let source: String = args.get(2, "URL or path")?;
+        if source.is_empty() {
+            return Err("expected a URL or path to play".to_string());
+        }
+        Ok(PlayInfo {
+            guild_id: args.get(0, "guild id")?,
+            channel_id: args.get(1, "channel id")?,
+            source,
+            volume: args.get_opt(3, "volume")?.unwrap_or(1.),
+        })
+    }
+
+    fn run(&self, play: PlayInfo) {
+        self.queue.lock().unwrap().push(play);
+    }
+}
+
+/// Copy the file at path in the game to the temporary directory for ffmpeg to read, as the
+/// game may be packed
+fn extract(loader: &dyn ScriptLoader, path: &str) -> Result<PathBuf, PlayError> {
+    let io_error = |e| PlayError::IoError(path.to_string(), e);
+    let mut hasher = DefaultHasher::new();
+    loader.locate(path).hash(&mut hasher);
+    let name = match Path::new(path).extension() {
+        Some(ext) => format!("image_rpg_{:x}.{}", hasher.finish(), ext.to_string_lossy()),
+        None => format!("image_rpg_{:x}", hasher.finish()),
+    };
+    let extracted = std::env::temp_dir().join(name);
+    fs::write(&extracted, loader.read(path).map_err(io_error)?).map_err(io_error)?;
+    Ok(extracted)
+}
+
+/// Play source on loop in the voice channel, streaming URLs and reading paths through loader
+pub async fn play(
     ctx: &Context,
+    loader: &dyn ScriptLoader,
     guild_id: u64,
     channel_id: u64,
-    url: &str,
+    source: &str,
     volume: f32,
 ) -> Result<(), PlayError> {
-    if !url.starts_with("http") {
-        return Err(PlayError::InvalidURL(url.to_string()));
-    }
+    let path = if source.starts_with("http") {
+        None
+    } else {
+        Some(extract(loader, source)?)
+    };
 
     let manager = songbird::get(ctx)
         .await
@@ -23,7 +89,11 @@ pub async fn play_url(
     if let Some(handler_lock) = manager.get(guild_id) {
         let mut handler = handler_lock.lock().await;
 
-        let source = match Restartable::ytdl(url.trim().to_owned(), false).await {
+        let source = match path {
+            Some(path) => Restartable::ffmpeg(path, false).await,
+            None => Restartable::ytdl(source.trim().to_owned(), false).await,
+        };
+        let source = match source {
             Ok(source) => source,
             Err(why) => return Err(PlayError::InputError(why)),
         };
@@ -0,0 +1,103 @@
+use std::{iter::Peekable, str::Chars};
+
+use super::ParseError;
+
+/// Split the arguments of a directive on commas, reporting errors on behalf of `directive`
+///
+/// Unquoted arguments are trimmed. Arguments wrapped in double quotes keep their
+/// whitespace and commas. `\"`, `\\`, `\,`, `\n` and `\t` are escapes in both.
+pub fn split_args(directive: &'static str, ctx: &str) -> Result<Vec<String>, ParseError> {
+    let error = |why: &str| ParseError::DirectiveError(directive, why.to_string());
+    if ctx.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut args = Vec::new();
+    let mut chars = ctx.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let mut arg = String::new();
+        let end = if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => {
+                        arg.push(unescape(&mut chars).ok_or_else(|| error("dangling \\"))?)
+                    }
+                    Some(c) => arg.push(c),
+                    None => return Err(error("unterminated string")),
+                }
+            }
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            match chars.next() {
+                None => true,
+                Some(',') => false,
+                Some(c) => {
+                    return Err(ParseError::DirectiveError(
+                        directive,
+                        format!("unexpected {} after closing quote", c),
+                    ))
+                }
+            }
+        } else {
+            // Length of the argument without its trailing unescaped whitespace
+            let mut len = 0;
+            let end = loop {
+                match chars.next() {
+                    Some(',') => break false,
+                    None => break true,
+                    Some('\\') => {
+                        arg.push(unescape(&mut chars).ok_or_else(|| error("dangling \\"))?);
+                        len = arg.len();
+                    }
+                    Some(c) => {
+                        arg.push(c);
+                        if !c.is_whitespace() {
+                            len = arg.len();
+                        }
+                    }
+                }
+            };
+            arg.truncate(len);
+            end
+        };
+
+        args.push(arg);
+        if end {
+            return Ok(args);
+        }
+    }
+}
+
+fn unescape(chars: &mut Peekable<Chars>) -> Option<char> {
+    Some(match chars.next()? {
+        'n' => '\n',
+        't' => '\t',
+        c => c,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::split_args;
+
+    #[test]
+    fn split() {
+        let split = |s: &str| split_args("test", s).unwrap();
+        assert!(split("  ").is_empty());
+        assert_eq!(split(" a , b c ,"), ["a", "b c", ""]);
+        assert_eq!(
+            split(r#""Wait, what?", "  Run ", x.txt"#),
+            ["Wait, what?", "  Run ", "x.txt"]
+        );
+        assert_eq!(
+            split(r#"a\, b, "say \"hi\"", c\ "#),
+            ["a, b", "say \"hi\"", "c "]
+        );
+        assert_eq!(split(r#""", """#), ["", ""]);
+        assert!(split_args("test", r#""open"#).is_err());
+        assert!(split_args("test", r#""a" b"#).is_err());
+        assert!(split_args("test", r"a\").is_err());
+    }
+}
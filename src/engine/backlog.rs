@@ -0,0 +1,49 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Dialogue shown or choice made while playing
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BacklogEntry {
+    /// Script containing the dialogue or choice
+    pub script: String,
+    /// Position of the dialogue or choice within the script
+    pub index: usize,
+    pub kind: BacklogKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BacklogKind {
+    /// Dialogue with its attributes and variables substituted as shown
+    Dialogue {
+        character: String,
+        text: String,
+    },
+    Choice {
+        choices: Vec<String>,
+        picked: usize,
+    },
+}
+
+impl BacklogEntry {
+    /// Text of the picked option if the entry is a choice
+    pub fn picked(&self) -> Option<&str> {
+        match &self.kind {
+            BacklogKind::Choice { choices, picked } => choices.get(*picked).map(String::as_str),
+            BacklogKind::Dialogue { .. } => None,
+        }
+    }
+}
+
+/// Format the entry as a line of a transcript
+impl fmt::Display for BacklogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            BacklogKind::Dialogue { character, text } if character.trim().is_empty() => {
+                write!(f, "{}", text)
+            }
+            BacklogKind::Dialogue { character, text } => write!(f, "{}: {}", character, text),
+            BacklogKind::Choice { .. } => write!(f, "> {}", self.picked().unwrap_or_default()),
+        }
+    }
+}
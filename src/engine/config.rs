@@ -1,8 +1,6 @@
-use std::{collections::HashMap, fs, io, process::exit};
+use std::collections::HashMap;
 
-use log::error;
-
-use super::ParseError;
+use super::{FileLoader, ParseError, ScriptLoader};
 
 #[derive(Debug)]
 pub struct Config {
@@ -11,19 +9,20 @@ pub struct Config {
 
 impl Config {
     pub fn from_file(path: &str) -> Result<Self, ParseError> {
+        Self::load(path, &FileLoader::default())
+    }
+
+    /// Parse the config at path read through loader
+    pub fn load(path: &str, loader: &dyn ScriptLoader) -> Result<Self, ParseError> {
+        Self::parse(path, &loader.load(path)?)
+    }
+
+    /// Parse a config from its source, name is used to report errors
+    pub fn parse(name: &str, source: &str) -> Result<Self, ParseError> {
         let mut fields = HashMap::new();
         let mut last_key: Option<&str> = None;
 
-        for (i, line) in fs::read_to_string(path)
-            .map_err(|e| match e {
-                _ if e.kind() == io::ErrorKind::NotFound => {
-                    ParseError::NoFileExists(path.to_owned())
-                }
-                _ => {
-                    error!("Cannot open file {} because {}", path, e);
-                    exit(1);
-                }
-            })?
+        for (i, line) in source
             .split('\n')
             .enumerate()
             .map(|(i, line)| (i + 1, line))
@@ -36,7 +35,7 @@ impl Config {
                 last_key = line.get(
                     1..line.rfind(']').ok_or_else(|| {
                         ParseError::SyntaxError(
-                            path.to_string(),
+                            name.to_string(),
                             i,
                             line.len(),
                             "Expected closing ]".into(),
@@ -54,7 +53,7 @@ impl Config {
                         .insert(key.trim().to_string(), value.trim().to_string());
                 } else {
                     return Err(ParseError::SyntaxError(
-                        path.to_string(),
+                        name.to_string(),
                         i,
                         line.len(),
                         "Values must be separated by =".into(),
@@ -0,0 +1,203 @@
+use std::{collections::HashMap, fmt, str::FromStr, sync::Arc};
+
+use super::{CustomDirective, ParseError, Script, ScriptContext, ScriptDirective, SyntaxError};
+
+/// Arguments of a custom directive, with typed accessors
+#[derive(Clone, Copy, Debug)]
+pub struct CustomArgs<'a> {
+    pub name: &'a str,
+    pub args: &'a [String],
+}
+
+/// Handles a custom directive `@custom(name(args))` registered under its name
+pub trait CustomHandler: Send + Sync + 'static {
+    type Args;
+
+    /// Parse and check the arguments, called for every use of the directive when a script is loaded
+    fn parse(&self, args: CustomArgs) -> Result<Self::Args, String>;
+
+    /// Called with the parsed arguments when the engine reaches the directive
+    fn run(&self, args: Self::Args);
+}
+
+/// `CustomHandler` with its arguments type erased so handlers can share a registry
+trait DynHandler: Send + Sync {
+    fn validate(&self, args: CustomArgs) -> Result<(), String>;
+    fn run(&self, args: CustomArgs) -> Result<(), String>;
+}
+
+impl<H: CustomHandler> DynHandler for H {
+    fn validate(&self, args: CustomArgs) -> Result<(), String> {
+        self.parse(args).map(|_| ())
+    }
+
+    fn run(&self, args: CustomArgs) -> Result<(), String> {
+        CustomHandler::run(self, self.parse(args)?);
+        Ok(())
+    }
+}
+
+/// Handlers of custom directives by name. Custom directives without a handler are ignored
+#[derive(Clone, Default)]
+pub struct CustomRegistry {
+    handlers: HashMap<String, Arc<dyn DynHandler>>,
+}
+
+impl<'a> CustomArgs<'a> {
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    /// Parse the argument at index, describing it as what in errors
+    pub fn get<T: FromStr>(&self, index: usize, what: &str) -> Result<T, String> {
+        self.get_opt(index, what)?
+            .ok_or_else(|| format!("expected {} as argument {}", what, index + 1))
+    }
+
+    /// Parse the argument at index if it is given
+    pub fn get_opt<T: FromStr>(&self, index: usize, what: &str) -> Result<Option<T>, String> {
+        self.args
+            .get(index)
+            .map(|arg| {
+                arg.parse()
+                    .map_err(|_| format!("cannot parse {} \"{}\"", what, arg))
+            })
+            .transpose()
+    }
+
+    /// Fail if more than max arguments are given
+    pub fn at_most(&self, max: usize) -> Result<(), String> {
+        if self.args.len() > max {
+            return Err(format!(
+                "{} expects at most {} arguments, got {}",
+                self.name,
+                max,
+                self.args.len()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<'a> From<&'a CustomDirective> for CustomArgs<'a> {
+    fn from(custom: &'a CustomDirective) -> Self {
+        Self {
+            name: &custom.name,
+            args: &custom.args,
+        }
+    }
+}
+
+impl CustomRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle custom directives called name with handler, replacing any previous handler
+    pub fn register<H: CustomHandler>(&mut self, name: &str, handler: H) -> &mut Self {
+        self.handlers.insert(name.to_string(), Arc::new(handler));
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.handlers.contains_key(name)
+    }
+
+    /// Check the arguments of every handled custom directive in script
+    pub fn validate(&self, script: &Script) -> Result<(), ParseError> {
+        let errors = script
+            .ctx
+            .iter()
+            .zip(&script.spans)
+            .filter_map(|(ctx, span)| match ctx {
+                ScriptContext::Directive(ScriptDirective::Custom(custom)) => {
+                    let handler = self.handlers.get(&custom.name)?;
+                    handler
+                        .validate(custom.into())
+                        .err()
+                        .map(|why| SyntaxError {
+                            span: span.clone(),
+                            why: format!("Invalid custom directive {}: {}", custom.name, why),
+                        })
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        if !errors.is_empty() {
+            return Err(ParseError::Diagnostics(errors));
+        }
+        Ok(())
+    }
+
+    /// Run the handler of custom, if any
+    pub fn run(&self, custom: &CustomDirective) -> Result<(), String> {
+        match self.handlers.get(&custom.name) {
+            Some(handler) => handler.run(custom.into()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for CustomRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.handlers.keys()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{
+        engine::{ParseError, ScriptContext, ScriptDirective},
+        Script,
+    };
+
+    use super::{CustomArgs, CustomHandler, CustomRegistry};
+
+    struct Wait(Arc<Mutex<Vec<u32>>>);
+
+    impl CustomHandler for Wait {
+        type Args = u32;
+
+        fn parse(&self, args: CustomArgs) -> Result<u32, String> {
+            args.at_most(1)?;
+            args.get(0, "milliseconds")
+        }
+
+        fn run(&self, ms: u32) {
+            self.0.lock().unwrap().push(ms);
+        }
+    }
+
+    #[test]
+    fn registry() {
+        let waited = Arc::new(Mutex::new(Vec::new()));
+        let mut registry = CustomRegistry::new();
+        registry.register("wait", Wait(waited.clone()));
+
+        let script = Script::parse(
+            "a.txt",
+            "@custom(wait(500))\n@custom(other(x))\n@custom(wait(soon))\n",
+        )
+        .unwrap();
+        let errors = match registry.validate(&script) {
+            Err(ParseError::Diagnostics(errors)) => errors,
+            result => panic!("expected diagnostics, got {:?}", result),
+        };
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span.line, 3);
+
+        for ctx in &script.ctx[..2] {
+            if let ScriptContext::Directive(ScriptDirective::Custom(custom)) = ctx {
+                registry.run(custom).unwrap();
+            }
+        }
+        assert_eq!(*waited.lock().unwrap(), [500]);
+    }
+}
@@ -1,6 +1,13 @@
+use std::fmt;
+
 use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+use super::{args::split_args, expr::Expr, frame::parse_color, ParseError, Script, ScriptLoader};
 
-use super::{ParseError, Script};
+/// Most choices a jump may present. Discord shows at most 5 rows of 5 buttons, and one row is
+/// kept for the back button
+pub const MAX_CHOICES: usize = 20;
 
 pub trait Directive: Sized {
     fn from_context(ctx: &str) -> Result<Self, ParseError>;
@@ -8,20 +15,38 @@ pub trait Directive: Sized {
 
 #[derive(Clone, Debug)]
 pub struct JumpDirective {
-    pub choices: Option<(String, String)>,
-    pub endpoint: LazilyLoadedScript,
+    /// Choices presented to the player, or `None` for an unconditional jump
+    pub choices: Option<Vec<JumpChoice>>,
+    /// Script to jump to when there are no choices
+    pub endpoint: Option<LazilyLoadedScript>,
+}
+
+#[derive(Clone, Debug)]
+pub struct JumpChoice {
+    pub text: String,
+    /// Script to jump to if taken, or `None` to continue the current script
+    pub endpoint: Option<LazilyLoadedScript>,
 }
 
 #[derive(Clone, Debug)]
 pub struct LazilyLoadedScript {
+    /// Path of the script file, empty if the jump stays in the current script
     pub script_path: String,
+    /// Label to start from inside the script, or its beginning if none
+    pub label: Option<String>,
     pub script: Option<Script>,
 }
 
-#[derive(Hash, Clone, Debug)]
+#[derive(Clone, Debug)]
+pub struct LabelDirective {
+    pub name: String,
+}
+
+#[derive(Hash, Clone, Debug, Serialize, Deserialize)]
 pub struct SpriteDirective {
     pub name: String,
     pub sprite_path: Option<String>,
+    #[serde(skip)]
     pub sprite: Option<DynamicImage>,
     pub x: Option<u32>,
     pub y: Option<u32>,
@@ -47,6 +72,29 @@ pub struct AttributeDirective {
     pub value: String,
 }
 
+#[derive(Clone, Debug)]
+pub struct SetDirective {
+    pub path: String,
+    pub key: String,
+    pub value: Expr,
+}
+
+#[derive(Clone, Debug)]
+pub struct ConditionDirective {
+    pub condition: Expr,
+}
+
+#[derive(Clone, Debug)]
+pub struct IncludeDirective {
+    pub path: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct MacroDirective {
+    pub name: String,
+    pub params: Vec<String>,
+}
+
 #[derive(Clone, Debug)]
 pub struct CustomDirective {
     pub name: String,
@@ -55,41 +103,115 @@ pub struct CustomDirective {
 
 impl Directive for JumpDirective {
     /// Return a jump directive from context
-    /// "A", "B", endpoint.script to jump to endpoint.script if A is taken or
-    /// endpoint.script
+    /// endpoint.script to jump to endpoint.script unconditionally,
+    /// "A", a.script, "B", b.script, ... to jump to the script of the taken choice or
+    /// "A", "B", endpoint.script to jump to endpoint.script if A is taken
+    /// An empty choice endpoint continues the current script
+    /// An endpoint may target a label with endpoint.script#label or #label
+    /// At most `MAX_CHOICES` choices may be given
     fn from_context(ctx: &str) -> Result<Self, ParseError> {
-        let ctx = ctx.split(',').take(3).collect::<Vec<_>>();
-        let (choices, endpoint) = match &ctx[..] {
-            [a, b, endpoint] => (Some((a.trim().to_string(), b.trim().to_string())), endpoint),
-            [endpoint] => (None, endpoint),
+        let args = split_args("jump", ctx)?;
+        let endpoint =
+            |endpoint: &str| (!endpoint.is_empty()).then(|| LazilyLoadedScript::new(endpoint));
+
+        Ok(match &args[..] {
+            [target] => Self {
+                choices: None,
+                endpoint: Some(endpoint(target).ok_or_else(|| {
+                    ParseError::DirectiveError("jump", "expected a script to jump to".into())
+                })?),
+            },
+            [a, b, target] => Self {
+                choices: Some(vec![
+                    JumpChoice {
+                        text: a.to_string(),
+                        endpoint: endpoint(target),
+                    },
+                    JumpChoice {
+                        text: b.to_string(),
+                        endpoint: None,
+                    },
+                ]),
+                endpoint: None,
+            },
+            pairs if pairs.len() / 2 > MAX_CHOICES => {
+                return Err(ParseError::DirectiveError(
+                    "jump",
+                    format!(
+                        "jump directive presents at most {} choices, got {}",
+                        MAX_CHOICES,
+                        pairs.len() / 2
+                    ),
+                ))
+            }
+            pairs if !pairs.is_empty() && pairs.len() % 2 == 0 => Self {
+                choices: Some(
+                    pairs
+                        .chunks(2)
+                        .map(|pair| JumpChoice {
+                            text: pair[0].to_string(),
+                            endpoint: endpoint(&pair[1]),
+                        })
+                        .collect(),
+                ),
+                endpoint: None,
+            },
             _ => {
                 return Err(ParseError::DirectiveError(
                     "jump",
-                    format!("jump directive expects 1 or 3 arguments, got {}", ctx.len()),
+                    format!(
+                        "jump directive expects 1, 3 or an even number of arguments, got {}",
+                        args.len()
+                    ),
                 ))
             }
-        };
-        Ok(Self {
-            choices,
-            endpoint: LazilyLoadedScript::new(&endpoint.split_whitespace().collect::<String>()),
         })
     }
 }
 
+impl JumpDirective {
+    /// Every script this directive may jump to
+    pub fn endpoints(&self) -> impl Iterator<Item = &LazilyLoadedScript> {
+        self.endpoint.iter().chain(
+            self.choices
+                .iter()
+                .flatten()
+                .filter_map(|choice| choice.endpoint.as_ref()),
+        )
+    }
+
+    pub fn endpoints_mut(&mut self) -> impl Iterator<Item = &mut LazilyLoadedScript> {
+        self.endpoint.iter_mut().chain(
+            self.choices
+                .iter_mut()
+                .flatten()
+                .filter_map(|choice| choice.endpoint.as_mut()),
+        )
+    }
+}
+
 impl Directive for SpriteDirective {
     /// Return a sprite directive from context
     /// name,display,x,y,show|hide
     fn from_context(ctx: &str) -> Result<Self, ParseError> {
-        let ctx = ctx.split_whitespace().collect::<String>();
+        let args = split_args("sprite", ctx)?;
+        let coordinate = |axis: &str, value: &str| {
+            value.parse().map_err(|_| {
+                ParseError::DirectiveError(
+                    "sprite",
+                    format!("{} must be an integer, got {}", axis, value),
+                )
+            })
+        };
 
         Ok(
-            match &ctx.split(',').map(str::trim).collect::<Vec<&str>>()[..] {
+            match &args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
                 [name, display, x, y, visibility] => Self {
                     name: name.to_string(),
                     sprite_path: Some(display.to_string()),
                     sprite: None,
-                    x: Some(x.parse().expect("x must be an integer")),
-                    y: Some(y.parse().expect("y must be an integer")),
+                    x: Some(coordinate("x", x)?),
+                    y: Some(coordinate("y", y)?),
                     show: match *visibility {
                         "show" => true,
                         "hide" => false,
@@ -131,9 +253,15 @@ impl Directive for SpriteDirective {
 
 impl Directive for LoadBGDirective {
     fn from_context(ctx: &str) -> Result<Self, ParseError> {
-        Ok(Self {
-            bg_path: ctx.to_owned(),
-        })
+        match &split_args("loadbg", ctx)?[..] {
+            [bg_path] => Ok(Self {
+                bg_path: bg_path.to_string(),
+            }),
+            _ => Err(ParseError::DirectiveError(
+                "loadbg",
+                "expected path of the background".to_string(),
+            )),
+        }
     }
 }
 
@@ -142,14 +270,12 @@ impl Directive for CustomDirective {
         let directive_iend = ctx
             .find('(')
             .ok_or_else(|| ParseError::DirectiveError("custom", "expected opening (".into()))?;
-        let directive = ctx.get(..directive_iend).unwrap().to_string();
-        let args = ctx
-            .get(directive_iend + 1..ctx.len() - 1)
-            .unwrap()
-            .split(',')
-            .map(str::trim)
-            .map(str::to_string)
-            .collect();
+        let directive = ctx.get(..directive_iend).unwrap().trim().to_string();
+        let args = ctx[directive_iend + 1..]
+            .trim_end()
+            .strip_suffix(')')
+            .ok_or_else(|| ParseError::DirectiveError("custom", "expected closing )".into()))?;
+        let args = split_args("custom", args)?;
 
         Ok(Self {
             name: directive,
@@ -160,26 +286,19 @@ impl Directive for CustomDirective {
 
 impl Directive for CharacterAttributeDirective {
     fn from_context(ctx: &str) -> Result<Self, ParseError> {
-        let mut args = ctx.split(',').take(3);
-        let character = args.next().ok_or_else(|| {
-            ParseError::DirectiveError(
-                "cattr",
-                "expected character to set attribute to".to_string(),
-            )
-        })?;
-        let attribute = args
-            .next()
-            .ok_or_else(|| ParseError::DirectiveError("cattr", "expected attribute".to_string()))?
-            .trim();
-        let value = args
-            .next()
-            .ok_or_else(|| {
-                ParseError::DirectiveError("cattr", "expected value of attribute".to_string())
-            })?
-            .trim();
+        let args = split_args("cattr", ctx)?;
+        let (character, attribute, value) = match &args[..] {
+            [character, attribute, value] => (character, attribute.as_str(), value),
+            _ => {
+                return Err(ParseError::DirectiveError(
+                    "cattr",
+                    "expected character, attribute and value".to_string(),
+                ))
+            }
+        };
 
         let mut cattr = Self {
-            character: character.trim().to_string(),
+            character: character.to_string(),
             text_color: None,
             dialogue_color: None,
         };
@@ -204,35 +323,239 @@ impl Directive for CharacterAttributeDirective {
     }
 }
 
+impl Directive for LabelDirective {
+    fn from_context(ctx: &str) -> Result<Self, ParseError> {
+        match &split_args("label", ctx)?[..] {
+            [name] if !name.is_empty() => Ok(Self {
+                name: name.to_string(),
+            }),
+            _ => Err(ParseError::DirectiveError(
+                "label",
+                "expected label name".to_string(),
+            )),
+        }
+    }
+}
+
 impl LazilyLoadedScript {
-    pub fn new(script_path: &str) -> Self {
+    /// Create a lazily loaded script from a jump target
+    /// path, path#label or #label
+    pub fn new(target: &str) -> Self {
+        let (script_path, label) = match target.split_once('#') {
+            Some((script_path, label)) => (script_path, Some(label.to_string())),
+            None => (target, None),
+        };
         Self {
             script_path: script_path.to_string(),
+            label,
             script: None,
         }
     }
 
-    pub fn load(&mut self) -> Script {
-        self.script
-            .get_or_insert(Script::from_file(&self.script_path).unwrap())
-            .clone()
+    /// Whether the target is a label in the script containing the jump
+    pub fn is_local(&self) -> bool {
+        self.script_path.is_empty()
+    }
+
+    /// Load the script through loader, parsing it only on the first load
+    pub fn load(&mut self, loader: &dyn ScriptLoader) -> Result<Script, ParseError> {
+        match &self.script {
+            Some(script) => Ok(script.clone()),
+            None => Ok(self
+                .script
+                .insert(Script::load(&self.script_path, loader)?)
+                .clone()),
+        }
+    }
+}
+
+impl fmt::Display for LazilyLoadedScript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.label {
+            Some(label) => write!(f, "{}#{}", self.script_path, label),
+            None => write!(f, "{}", self.script_path),
+        }
     }
 }
 
 impl Directive for AttributeDirective {
     fn from_context(ctx: &str) -> Result<Self, ParseError> {
-        let mut pair = ctx.split(',').map(str::trim).map(str::to_string).take(2);
-        let path = pair
-            .next()
-            .ok_or_else(|| ParseError::DirectiveError("attr", "expected key".to_string()))?;
-        let value = pair
-            .next()
-            .ok_or_else(|| ParseError::DirectiveError("attr", "expected value".to_string()))?;
+        let args = split_args("attr", ctx)?;
+        let (path, value) = match &args[..] {
+            [path, value] => (path, value),
+            [] | [_] => {
+                return Err(ParseError::DirectiveError(
+                    "attr",
+                    "expected key and value".to_string(),
+                ))
+            }
+            _ => {
+                return Err(ParseError::DirectiveError(
+                    "attr",
+                    format!(
+                        "expected key and value, got {} arguments. Quote values containing commas",
+                        args.len()
+                    ),
+                ))
+            }
+        };
+        let (path, key) = path.split_at(path.rfind('.').unwrap_or(0));
+        let key = key.trim_matches('.');
+        // Catch malformed character colors when the script loads rather than when drawn
+        if path.split('.').next() == Some("character")
+            && matches!(key, "text_color" | "dialogue_color")
+            && parse_color(value).is_none()
+        {
+            return Err(ParseError::DirectiveError(
+                "attr",
+                format!("{} must be a hexadecimal RGBA color, got {}", key, value),
+            ));
+        }
+        Ok(Self {
+            path: path.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+impl Directive for SetDirective {
+    /// Return a set directive from context
+    /// path.to.variable, expression
+    fn from_context(ctx: &str) -> Result<Self, ParseError> {
+        let (path, value) = ctx.split_once(',').ok_or_else(|| {
+            ParseError::DirectiveError("set", "expected variable and value".to_string())
+        })?;
+        let path = path.trim();
+        if path.is_empty() {
+            return Err(ParseError::DirectiveError(
+                "set",
+                "expected variable".to_string(),
+            ));
+        }
         let (path, key) = path.split_at(path.rfind('.').unwrap_or(0));
         Ok(Self {
             path: path.to_string(),
             key: key.trim_matches('.').to_string(),
-            value,
+            value: Expr::parse("set", value)?,
+        })
+    }
+}
+
+impl Directive for ConditionDirective {
+    fn from_context(ctx: &str) -> Result<Self, ParseError> {
+        Ok(Self {
+            condition: Expr::parse("if", ctx)?,
         })
     }
 }
+
+impl Directive for IncludeDirective {
+    fn from_context(ctx: &str) -> Result<Self, ParseError> {
+        match &split_args("include", ctx)?[..] {
+            [path] if !path.is_empty() => Ok(Self {
+                path: path.to_string(),
+            }),
+            _ => Err(ParseError::DirectiveError(
+                "include",
+                "expected path of the script to include".to_string(),
+            )),
+        }
+    }
+}
+
+impl Directive for MacroDirective {
+    /// Return a macro directive from context
+    /// name, param1, param2, ...
+    fn from_context(ctx: &str) -> Result<Self, ParseError> {
+        let mut args = split_args("macro", ctx)?.into_iter();
+        let name = args
+            .next()
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| ParseError::DirectiveError("macro", "expected macro name".into()))?;
+        let params = args.collect::<Vec<_>>();
+        if let Some(param) = params
+            .iter()
+            .find(|p| p.is_empty() || !p.chars().all(|c| c.is_alphanumeric() || c == '_'))
+        {
+            return Err(ParseError::DirectiveError(
+                "macro",
+                format!("invalid parameter name \"{}\"", param),
+            ));
+        }
+
+        Ok(Self { name, params })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AttributeDirective, Directive, JumpDirective, LazilyLoadedScript, MAX_CHOICES};
+
+    #[test]
+    fn jump_targets() {
+        let target = LazilyLoadedScript::new("resources/a.txt#hall");
+        assert_eq!(target.script_path, "resources/a.txt");
+        assert_eq!(target.label.as_deref(), Some("hall"));
+
+        let target = LazilyLoadedScript::new("#hall");
+        assert!(target.is_local());
+        assert_eq!(target.label.as_deref(), Some("hall"));
+
+        let jump = JumpDirective::from_context("resources/a.txt").unwrap();
+        assert!(jump.choices.is_none());
+        assert_eq!(jump.endpoint.unwrap().script_path, "resources/a.txt");
+    }
+
+    #[test]
+    fn jump_choices() {
+        let jump = JumpDirective::from_context("Left, Right, resources/a.txt").unwrap();
+        let choices = jump.choices.unwrap();
+        assert_eq!(choices.len(), 2);
+        assert_eq!(
+            choices[0].endpoint.as_ref().unwrap().script_path,
+            "resources/a.txt"
+        );
+        assert!(choices[1].endpoint.is_none());
+
+        let jump = JumpDirective::from_context("A, a.txt, B, b.txt#x, C, ").unwrap();
+        let choices = jump.choices.unwrap();
+        assert_eq!(
+            choices.iter().map(|c| c.text.as_str()).collect::<Vec<_>>(),
+            ["A", "B", "C"]
+        );
+        assert_eq!(
+            choices[1].endpoint.as_ref().unwrap().label.as_deref(),
+            Some("x")
+        );
+        assert!(choices[2].endpoint.is_none());
+
+        assert!(JumpDirective::from_context("A, a.txt, B, b.txt, C").is_err());
+
+        let choices = |n: usize| vec!["Go, next.txt"; n].join(", ");
+        assert!(JumpDirective::from_context(&choices(MAX_CHOICES)).is_ok());
+        assert!(JumpDirective::from_context(&choices(MAX_CHOICES + 1)).is_err());
+
+        let jump = JumpDirective::from_context(r#""Wait, what?", "Run", x.txt"#).unwrap();
+        let choices = jump.choices.unwrap();
+        assert_eq!(choices[0].text, "Wait, what?");
+        assert_eq!(choices[0].endpoint.as_ref().unwrap().script_path, "x.txt");
+    }
+
+    #[test]
+    fn quoted_attributes() {
+        let attr = AttributeDirective::from_context(r#"john.motto, "Carpe diem, always""#).unwrap();
+        assert_eq!(
+            (attr.path.as_str(), attr.key.as_str(), attr.value.as_str()),
+            ("john", "motto", "Carpe diem, always")
+        );
+        assert!(AttributeDirective::from_context("john.motto, Carpe diem, always").is_err());
+    }
+
+    #[test]
+    fn character_colors() {
+        assert!(AttributeDirective::from_context("character.John.text_color, ff0000ff").is_ok());
+        assert!(AttributeDirective::from_context("character.John.dialogue_color, red").is_err());
+        assert!(AttributeDirective::from_context("john.text_color, red").is_ok());
+    }
+}
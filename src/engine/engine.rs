@@ -1,15 +1,29 @@
 use image::DynamicImage;
-use log::debug;
-use std::{collections::HashMap, fs, mem::swap, path::PathBuf};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt, fs,
+    path::PathBuf,
+    sync::Arc,
+};
 
 use super::{
+    backlog::{BacklogEntry, BacklogKind},
+    custom::{CustomHandler, CustomRegistry},
+    frame::{
+        character_color, character_font, Frame, FrameContent, FrameSprite, DEFAULT_DIALOGUE_COLOR,
+        DEFAULT_TEXT_COLOR,
+    },
+    markup::{self, plain_text, Run},
+    prefetch::{Prefetcher, DEFAULT_PREFETCH_BUDGET},
+    read::ReadLog,
+    save::{SaveData, SAVE_VERSION},
     script::{ScriptContext, ScriptDirective},
-    AttributeDirective, ParseError, Script, SpriteDirective,
-};
-use crate::{
-    img::{error::LoadImageError, load_image},
-    Scene,
+    AttributeDirective, EngineError, FileLoader, ParseError, SaveError, Script, ScriptLoader,
+    SpriteDirective,
 };
+use crate::{img::Assets, Scene};
 
 macro_rules! attr_cast {
     ($val:expr => $variant:path) => {
@@ -20,27 +34,63 @@ macro_rules! attr_cast {
     };
 }
 
+/// Number of frames `Engine::back` can return through
+const MAX_HISTORY: usize = 256;
+/// Number of contexts after the current one searched for images to prefetch
+const PREFETCH_LOOKAHEAD: usize = 64;
+
 pub struct Engine {
     pub script: Script,
+    loader: Arc<dyn ScriptLoader>,
     pub iscript: usize,
+    /// Page shown of the current dialogue
+    page: usize,
+    /// Runs of every page of the current dialogue, laid out when the engine lands on it.
+    /// Empty if the current context is not a dialogue
+    pages: Vec<Vec<Run>>,
     scene: Scene,
     sprites: Vec<SpriteDirective>,
-    cached_bgs: HashMap<String, DynamicImage>,
+    assets: Assets,
+    prefetcher: Prefetcher,
     bg_path: Option<String>,
+    /// Held so the current background is never evicted from the assets
+    bg: Option<Arc<DynamicImage>>,
     attributes: Attributes,
-    cache: Option<Vec<PathBuf>>,
+    /// Directory of cached renders along with the renders in it
+    cache: Option<(PathBuf, Vec<PathBuf>)>,
+    /// State at every renderable frame advanced from, oldest first
+    history: VecDeque<Snapshot>,
+    backlog: Vec<BacklogEntry>,
+    custom: CustomRegistry,
+    read: ReadLog,
 }
 
-#[derive(Clone, Copy, Debug)]
+/// State of the engine at a renderable frame
+struct Snapshot {
+    iscript: usize,
+    page: usize,
+    pages: Vec<Vec<Run>>,
+    sprites: Vec<SpriteDirective>,
+    bg_path: Option<String>,
+    bg: Option<Arc<DynamicImage>>,
+    attributes: Attributes,
+    backlog_len: usize,
+    /// Script the frame belongs to, only kept by the last frame before a jump replaced it.
+    /// Frames without one belong to the script of the frame after them
+    script: Option<Script>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Number {
     PosInt(u64),
     NegInt(i64),
     Float(f64),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Attributes(HashMap<String, AttributeValue>);
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
 pub enum AttributeValue {
     Attribute(Attributes),
     Value(String),
@@ -63,16 +113,10 @@ impl Attributes {
 
     pub fn get_path(&self, path: &str) -> Option<&AttributeValue> {
         let mut attrs = path.trim().split('.');
-        let mut ret = match self.get(attrs.next().unwrap()) {
-            Some(r) => r,
-            None => return None,
-        };
+        let mut ret = self.get(attrs.next().unwrap())?;
         for attr in attrs {
             if let Some(a) = ret.as_attribute() {
-                ret = match a.get(attr) {
-                    Some(a) => a,
-                    None => return None,
-                }
+                ret = a.get(attr)?;
             } else {
                 return None;
             }
@@ -80,21 +124,63 @@ impl Attributes {
         Some(ret)
     }
 
+    /// Replace every `{path}` in text with the value of the attribute at path.
+    /// Unknown paths are kept as is, and `{{` and `}}` escape a brace
+    pub fn interpolate(&self, text: &str) -> String {
+        let mut interpolated = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(start) = rest.find(&['{', '}'][..]) {
+            interpolated.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            if let Some(escaped) = rest.strip_prefix("{{").or_else(|| rest.strip_prefix("}}")) {
+                interpolated.push_str(&rest[..1]);
+                rest = escaped;
+                continue;
+            }
+            if let Some(unmatched) = rest.strip_prefix('}') {
+                interpolated.push('}');
+                rest = unmatched;
+                continue;
+            }
+
+            match rest.find('}').and_then(|end| {
+                self.get_path(&rest[1..end])
+                    .and_then(AttributeValue::as_value)
+                    .map(|value| (end, value))
+            }) {
+                Some((end, value)) => {
+                    interpolated.push_str(value);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    interpolated.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        interpolated.push_str(rest);
+
+        interpolated
+    }
+
     pub fn add_attribute(&mut self, attr: &AttributeDirective) {
         let mut attrs = attr.path.split('.');
         let s = attrs.next().unwrap();
 
         if !s.is_empty() {
-            if !self.0.contains_key(s) {
-                self.0
-                    .insert(s.to_string(), AttributeValue::Attribute(Self::new()));
-            }
-            let mut v = self.0.get_mut(s).unwrap().as_attribute_mut().unwrap();
-            for attr in attrs {
-                if !v.0.contains_key(attr) {
-                    v.0.insert(attr.to_string(), AttributeValue::Attribute(Self::new()));
+            let mut v = self;
+            for name in std::iter::once(s).chain(attrs) {
+                let entry =
+                    v.0.entry(name.to_string())
+                        .or_insert_with(|| AttributeValue::Attribute(Self::new()));
+                // A value in the way of the path is replaced by the attributes set under it
+                if !entry.is_attribute() {
+                    warn!("{} of {} is replaced by attributes", name, attr.path);
+                    *entry = AttributeValue::Attribute(Self::new());
                 }
-                v = attr_cast!(v.0.get_mut(attr).unwrap() => AttributeValue::Attribute);
+                v = attr_cast!(entry => AttributeValue::Attribute);
             }
             v.0.insert(attr.key.clone(), AttributeValue::Value(attr.value.clone()));
         } else {
@@ -151,59 +237,463 @@ impl Number {
             _ => None,
         }
     }
+
+    pub fn as_f64_lossy(&self) -> f64 {
+        match self {
+            Number::PosInt(u) => *u as f64,
+            Number::NegInt(i) => *i as f64,
+            Number::Float(f) => *f,
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if let Ok(u) = s.parse::<u64>() {
+            Some(Number::PosInt(u))
+        } else if let Ok(i) = s.parse::<i64>() {
+            Some(Number::NegInt(i))
+        } else {
+            s.parse::<f64>()
+                .ok()
+                .filter(|f| f.is_finite())
+                .map(Number::Float)
+        }
+    }
+
+    fn from_i128(i: i128) -> Self {
+        if let Ok(u) = u64::try_from(i) {
+            Number::PosInt(u)
+        } else if let Ok(i) = i64::try_from(i) {
+            Number::NegInt(i)
+        } else {
+            Number::Float(i as f64)
+        }
+    }
+
+    fn as_i128(&self) -> Option<i128> {
+        match self {
+            Number::PosInt(u) => Some(*u as i128),
+            Number::NegInt(i) => Some(*i as i128),
+            Number::Float(_) => None,
+        }
+    }
+
+    pub fn add(&self, other: &Number) -> Number {
+        match (self.as_i128(), other.as_i128()) {
+            (Some(a), Some(b)) => Number::from_i128(a + b),
+            _ => Number::Float(self.as_f64_lossy() + other.as_f64_lossy()),
+        }
+    }
+
+    pub fn neg(&self) -> Number {
+        match self.as_i128() {
+            Some(i) => Number::from_i128(-i),
+            None => Number::Float(-self.as_f64_lossy()),
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::PosInt(u) => write!(f, "{}", u),
+            Number::NegInt(i) => write!(f, "{}", i),
+            Number::Float(fl) => write!(f, "{}", fl),
+        }
+    }
 }
 
 impl Engine {
+    /// Create an engine starting at the script at path relative to the current directory
+    #[deprecated(note = "load the game relative to its root with `Engine::load`")]
     pub fn from_file(script_path: &str, scene: Scene) -> Result<Self, ParseError> {
-        Ok(Self {
-            script: Script::from_file(script_path)?,
+        Ok(Self::from_script(Script::from_file(script_path)?, scene))
+    }
+
+    /// Create an engine starting at the script at path, reading every file of the game through loader
+    pub fn load<L: ScriptLoader + 'static>(
+        path: &str,
+        scene: Scene,
+        loader: L,
+    ) -> Result<Self, ParseError> {
+        let mut engine = Self::from_script(Script::load(path, &loader)?, scene);
+        engine.set_loader(loader);
+        Ok(engine)
+    }
+
+    /// Create an engine starting at script, which loads jumped to scripts from the filesystem
+    pub fn from_script(script: Script, scene: Scene) -> Self {
+        let mut engine = Self {
+            script,
+            loader: Arc::new(FileLoader::default()),
             iscript: 0,
+            page: 0,
+            pages: Vec::new(),
             scene,
             sprites: Vec::new(),
-            cached_bgs: HashMap::new(),
+            assets: Assets::shared(),
+            prefetcher: Prefetcher::new(DEFAULT_PREFETCH_BUDGET),
             bg_path: None,
+            bg: None,
             attributes: Attributes::default(),
             cache: None,
-        })
+            history: VecDeque::new(),
+            backlog: Vec::new(),
+            custom: CustomRegistry::new(),
+            read: ReadLog::new(),
+        };
+        engine.check_fonts(&engine.script);
+        engine.paginate();
+        engine
+    }
+
+    /// Warn about the characters of script given a font the scene has no font named after
+    fn check_fonts(&self, script: &Script) {
+        for (ctx, span) in script.ctx.iter().zip(&script.spans) {
+            if let ScriptContext::Directive(ScriptDirective::Attr(attr)) = ctx {
+                if attr.path.split('.').next() == Some("character")
+                    && attr.key == "font"
+                    && !self.scene.fonts.contains_key(&attr.value)
+                {
+                    warn!(
+                        "No font is named {} on file \"{}\" line {}, using the scene's font",
+                        attr.value, span.file, span.line
+                    );
+                }
+            }
+        }
+    }
+
+    /// Load scripts jumped to through loader instead of the filesystem
+    pub fn set_loader<L: ScriptLoader + 'static>(&mut self, loader: L) {
+        self.loader = Arc::new(loader);
+    }
+
+    /// Share decoded images with the engines given the same assets instead of every engine
+    pub fn set_assets(&mut self, assets: Assets) {
+        self.assets = assets;
+    }
+
+    /// Decode at most bytes of upcoming backgrounds and sprites ahead of time, or none if 0
+    pub fn set_prefetch_budget(&mut self, bytes: usize) {
+        self.prefetcher = Prefetcher::new(bytes);
+    }
+
+    /// Run handler whenever a custom directive called name is reached.
+    /// Fails if a use of the directive in the current script has invalid arguments,
+    /// scripts jumped to are checked when they are loaded
+    pub fn register_custom<H: CustomHandler>(
+        &mut self,
+        name: &str,
+        handler: H,
+    ) -> Result<(), ParseError> {
+        self.custom.register(name, handler);
+        self.custom.validate(&self.script)
+    }
+
+    /// Snapshot the state needed to resume the story from the current context
+    pub fn save(&self) -> SaveData {
+        SaveData {
+            version: SAVE_VERSION,
+            script_path: self.script.name.clone(),
+            iscript: self.iscript,
+            page: self.page,
+            sprites: self
+                .sprites
+                .iter()
+                .map(|sprite| SpriteDirective {
+                    sprite: None,
+                    ..sprite.clone()
+                })
+                .collect(),
+            bg_path: self.bg_path.clone(),
+            attributes: self.attributes.clone(),
+            backlog: self.backlog.clone(),
+        }
+    }
+
+    /// Resume a saved story, loading scripts from the filesystem
+    pub fn restore(save: SaveData, scene: Scene) -> Result<Self, SaveError> {
+        Self::restore_with(save, scene, FileLoader::default())
+    }
+
+    /// Resume a saved story, loading scripts through loader
+    pub fn restore_with<L: ScriptLoader + 'static>(
+        save: SaveData,
+        scene: Scene,
+        loader: L,
+    ) -> Result<Self, SaveError> {
+        let script = Script::load(&save.script_path, &loader)?;
+        if save.iscript > script.ctx.len() {
+            return Err(SaveError::OutOfBounds(save.iscript, save.script_path));
+        }
+
+        let mut engine = Self::from_script(script, scene);
+        engine.set_loader(loader);
+        engine.iscript = save.iscript;
+        engine.page = save.page;
+        engine.sprites = save.sprites;
+        engine.attributes = save.attributes;
+        engine.backlog = save.backlog;
+        engine.bg = save
+            .bg_path
+            .as_ref()
+            .map(|bg_path| engine.assets.get(engine.loader.as_ref(), bg_path))
+            .transpose()?;
+        engine.bg_path = save.bg_path;
+        engine.paginate();
+        engine.prefetch();
+
+        Ok(engine)
     }
 
-    pub fn enable_cache(&mut self) {
-        fs::create_dir_all("resources/render/.cache").unwrap();
+    /// Reuse the renders of identical frames kept in dir by `cache_render_to`
+    pub fn enable_cache<P: Into<PathBuf>>(&mut self, dir: P) {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).unwrap();
 
         let mut cache = Vec::new();
-        for cache_info in fs::read_dir("resources/render/.cache").unwrap() {
+        for cache_info in fs::read_dir(&dir).unwrap() {
             cache.push(cache_info.unwrap().path());
         }
 
-        self.cache = Some(cache);
+        self.cache = Some((dir, cache));
+    }
+
+    /// Substitute the current attributes and variables into text
+    pub fn interpolate(&self, text: &str) -> String {
+        self.attributes.interpolate(text)
+    }
+
+    /// Split text into runs by its markup, then substitute the attributes and variables into
+    /// each run so their values are never read as markup
+    pub fn interpolate_markup(&self, text: &str) -> Vec<Run> {
+        markup::parse(text)
+            .into_iter()
+            .map(|run| Run {
+                text: self.interpolate(&run.text),
+                style: run.style,
+            })
+            .collect()
     }
 
     pub fn current(&self) -> Option<&ScriptContext> {
         self.script.ctx.get(self.iscript)
     }
 
-    pub fn next(&mut self, choice: bool) -> Result<Option<&ScriptContext>, LoadImageError> {
+    /// Every dialogue shown and choice made so far, oldest first
+    pub fn backlog(&self) -> std::slice::Iter<'_, BacklogEntry> {
+        self.backlog.iter()
+    }
+
+    /// Dialogues read so far, including those read in previous runs given to `set_read_log`
+    pub fn read_log(&self) -> &ReadLog {
+        &self.read
+    }
+
+    /// Continue tracking read dialogues from a log kept across runs
+    pub fn set_read_log(&mut self, read: ReadLog) {
+        self.read = read;
+    }
+
+    /// Whether the current context is a dialogue already read
+    pub fn is_read(&self) -> bool {
+        match self.current() {
+            Some(ScriptContext::Dialogue(dialogue)) => {
+                self.read.is_read(&self.script.name, dialogue)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether `back` has a frame to return to
+    pub fn has_history(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    /// Return to the previous renderable frame, restoring its sprites, background and attributes.
+    /// Returns `None` and leaves the engine untouched if there is no previous frame
+    pub fn back(&mut self) -> Option<&ScriptContext> {
+        let snapshot = self.history.pop_back()?;
+        if let Some(script) = snapshot.script {
+            self.script = script;
+        }
+        self.iscript = snapshot.iscript;
+        self.page = snapshot.page;
+        self.pages = snapshot.pages;
+        self.sprites = snapshot.sprites;
+        self.bg_path = snapshot.bg_path;
+        self.bg = snapshot.bg;
+        self.attributes = snapshot.attributes;
+        self.backlog.truncate(snapshot.backlog_len);
+        self.prefetch();
+        self.current()
+    }
+
+    fn is_renderable(ctx: &ScriptContext) -> bool {
+        match ctx {
+            ScriptContext::Dialogue(_) => true,
+            ScriptContext::Directive(ScriptDirective::Jump(jump)) => jump.choices.is_some(),
+            ScriptContext::Directive(_) => false,
+        }
+    }
+
+    /// Remember the current frame for `back` to return to
+    fn push_history(&mut self) {
+        if self.history.len() == MAX_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(Snapshot {
+            iscript: self.iscript,
+            page: self.page,
+            pages: self.pages.clone(),
+            sprites: self.sprites.clone(),
+            bg_path: self.bg_path.clone(),
+            bg: self.bg.clone(),
+            attributes: self.attributes.clone(),
+            backlog_len: self.backlog.len(),
+            script: None,
+        });
+    }
+
+    /// Lay out the current dialogue into pages, or clear them if it is not a dialogue
+    fn paginate(&mut self) {
+        self.pages = match self.current() {
+            Some(ScriptContext::Dialogue(dialogue)) => self.scene.paginate(
+                &self.interpolate_markup(&dialogue.dialogues.join(" ")),
+                character_font(&self.attributes, &dialogue.character_name).as_deref(),
+            ),
+            _ => Vec::new(),
+        };
+    }
+
+    /// Page shown of the current dialogue counting from 0 and its number of pages,
+    /// or `None` if it is not a dialogue
+    pub fn page(&self) -> Option<(usize, usize)> {
+        let pages = self.pages.len();
+        (pages > 0).then(|| (self.page.min(pages - 1), pages))
+    }
+
+    /// Advance the script by one context, taking the choice at index `choice`
+    /// if the current context presents choices. Dialogues split into pages advance a page
+    /// at a time
+    pub fn next(&mut self, choice: Option<usize>) -> Result<Option<&ScriptContext>, EngineError> {
+        if let Some((page, pages)) = self.page() {
+            if page + 1 < pages {
+                self.push_history();
+                self.page = page + 1;
+                return Ok(self.current());
+            }
+        }
+        let pushed = self.current().is_some_and(Self::is_renderable);
+        if let Some(ctx) = self.current().filter(|ctx| Self::is_renderable(ctx)) {
+            let entry = match ctx {
+                ScriptContext::Dialogue(dialogue) => Some(BacklogKind::Dialogue {
+                    character: dialogue.character_name.clone(),
+                    text: plain_text(&self.interpolate_markup(&dialogue.dialogues.join(" "))),
+                }),
+                ScriptContext::Directive(ScriptDirective::Jump(jump)) => {
+                    jump.choices.as_ref().and_then(|choices| {
+                        choice
+                            .filter(|choice| *choice < choices.len())
+                            .map(|picked| BacklogKind::Choice {
+                                choices: choices.iter().map(|choice| choice.text.clone()).collect(),
+                                picked,
+                            })
+                    })
+                }
+                ScriptContext::Directive(_) => None,
+            };
+
+            self.push_history();
+            self.page = 0;
+            if let Some(ScriptContext::Dialogue(dialogue)) = self.script.ctx.get(self.iscript) {
+                self.read.mark(&self.script.name, dialogue);
+            }
+            if let Some(kind) = entry {
+                self.backlog.push(BacklogEntry {
+                    script: self.script.name.clone(),
+                    index: self.iscript,
+                    kind,
+                });
+            }
+        }
+
+        if let Err(e) = self.step(choice) {
+            // Leave the engine at the frame it was on
+            if pushed {
+                self.back();
+            }
+            return Err(e);
+        }
+        self.paginate();
+        // Decode what comes next while the player reads the frame
+        if self.current().is_some_and(Self::is_renderable) {
+            self.prefetch();
+        }
+        Ok(self.script.ctx.get(self.iscript))
+    }
+
+    /// Carry out the current context, moving to the context that follows it
+    fn step(&mut self, choice: Option<usize>) -> Result<(), EngineError> {
         if let Some(ctx) = self.script.ctx.get_mut(self.iscript) {
             if let ScriptContext::Directive(directive) = ctx {
                 match directive {
-                    ScriptDirective::Jump(jump) => match &jump.choices {
-                        Some(_) => {
-                            if choice {
-                                self.script = jump.endpoint.load();
-                                self.iscript = 0;
-                            } else {
-                                self.iscript += 1
+                    ScriptDirective::Jump(jump) => {
+                        let endpoint = match &mut jump.choices {
+                            Some(choices) => choice
+                                .and_then(|choice| choices.get_mut(choice))
+                                .and_then(|choice| choice.endpoint.as_mut()),
+                            None => jump.endpoint.as_mut(),
+                        };
+                        if let Some(endpoint) = endpoint {
+                            let label = endpoint.label.clone();
+                            let script = match endpoint.is_local() {
+                                true => None,
+                                false => Some(
+                                    endpoint
+                                        .load(self.loader.as_ref())
+                                        .and_then(|script| {
+                                            self.custom.validate(&script)?;
+                                            Ok(script)
+                                        })
+                                        .map_err(|e| {
+                                            EngineError::Script(endpoint.to_string(), e)
+                                        })?,
+                                ),
+                            };
+                            if let Some(script) = &script {
+                                self.check_fonts(script);
                             }
+                            // Find the label before leaving the current script
+                            let target = script.as_ref().unwrap_or(&self.script);
+                            let iscript = match label {
+                                Some(label) => target.label(&label).ok_or_else(|| {
+                                    EngineError::MissingLabel(label, target.name.clone())
+                                })?,
+                                None => 0,
+                            };
+                            if let Some(script) = script {
+                                let previous = std::mem::replace(&mut self.script, script);
+                                // Keep the replaced script for the frames shown from it
+                                if let Some(snapshot) = self.history.back_mut() {
+                                    snapshot.script.get_or_insert(previous);
+                                }
+                            }
+                            self.iscript = iscript;
+                        } else {
+                            self.iscript += 1
                         }
-                        None => {
-                            self.iscript = 0;
-                            self.script = jump.endpoint.load()
-                        }
-                    },
+                    }
                     ScriptDirective::Sprite(sprite) => {
+                        if let Some(path) = &sprite.sprite_path {
+                            if let Some(image) = self.prefetcher.take(path) {
+                                self.assets.insert(self.loader.as_ref(), path, image);
+                            }
+                        }
                         if let Some(d) = self.sprites.iter_mut().position(|s| s.name == sprite.name)
                         {
-                            swap(&mut self.sprites[d], sprite);
+                            self.sprites[d] = sprite.clone();
                         } else {
                             let priority = |name: &str| {
                                 self.attributes
@@ -232,10 +722,12 @@ impl Engine {
                     }
                     ScriptDirective::LoadBG(bg) => {
                         self.bg_path = Some(bg.bg_path.to_string());
-                        if !self.cached_bgs.contains_key(&bg.bg_path) {
-                            self.cached_bgs
-                                .insert(bg.bg_path.to_string(), load_image(&bg.bg_path)?);
-                        }
+                        self.bg = Some(match self.prefetcher.take(&bg.bg_path) {
+                            Some(image) => {
+                                self.assets.insert(self.loader.as_ref(), &bg.bg_path, image)
+                            }
+                            None => self.assets.get(self.loader.as_ref(), &bg.bg_path)?,
+                        });
                         self.iscript += 1;
                     }
                     ScriptDirective::Attr(attr) => {
@@ -251,7 +743,32 @@ impl Engine {
                         }
                         self.iscript += 1;
                     }
-                    ScriptDirective::Custom(_) => {
+                    ScriptDirective::Set(set) => {
+                        let value = set.value.eval(&self.attributes).to_string();
+                        self.attributes.add_attribute(&AttributeDirective {
+                            path: set.path.clone(),
+                            key: set.key.clone(),
+                            value,
+                        });
+                        self.iscript += 1;
+                    }
+                    ScriptDirective::If(cond) => {
+                        if cond.condition.eval(&self.attributes).is_truthy() {
+                            self.iscript += 1;
+                        } else {
+                            self.iscript = self.branch_after(self.iscript);
+                        }
+                    }
+                    ScriptDirective::Elif(_) | ScriptDirective::Else => {
+                        self.iscript = self.conditional_end(self.iscript);
+                    }
+                    ScriptDirective::Custom(custom) => {
+                        self.custom
+                            .run(custom)
+                            .map_err(|e| EngineError::Custom(custom.name.clone(), e))?;
+                        self.iscript += 1;
+                    }
+                    ScriptDirective::Label(_) | ScriptDirective::EndIf => {
                         self.iscript += 1;
                     }
                 }
@@ -259,10 +776,72 @@ impl Engine {
                 self.iscript += 1;
             }
         }
-        Ok(self.script.ctx.get(self.iscript))
+        Ok(())
+    }
+
+    /// Start decoding the backgrounds and sprites shown after the current context,
+    /// up to the next jump as where the story goes from there is unknown
+    fn prefetch(&mut self) {
+        if self.prefetcher.budget() == 0 {
+            return;
+        }
+        let mut paths = Vec::new();
+        for ctx in self
+            .script
+            .ctx
+            .iter()
+            .skip(self.iscript + 1)
+            .take(PREFETCH_LOOKAHEAD)
+        {
+            let path = match ctx {
+                ScriptContext::Directive(ScriptDirective::LoadBG(bg))
+                    if !self.assets.contains(self.loader.as_ref(), &bg.bg_path) =>
+                {
+                    &bg.bg_path
+                }
+                ScriptContext::Directive(ScriptDirective::Sprite(SpriteDirective {
+                    sprite_path: Some(path),
+                    ..
+                })) if !self.assets.contains(self.loader.as_ref(), path) => path,
+                ScriptContext::Directive(ScriptDirective::Jump(_)) => break,
+                _ => continue,
+            };
+            if !paths.contains(path) {
+                paths.push(path.clone());
+            }
+        }
+        self.prefetcher.prefetch(paths, self.loader.clone());
     }
 
-    pub fn next_until<P>(&mut self, predicate: P) -> Result<Option<&ScriptContext>, LoadImageError>
+    /// Position to continue from when the conditional branch at `from` is not taken
+    fn branch_after(&self, from: usize) -> usize {
+        let mut pos = from;
+        while let Some(next) = self.script.next_branch(pos) {
+            match &self.script.ctx[next] {
+                ScriptContext::Directive(ScriptDirective::Elif(cond))
+                    if !cond.condition.eval(&self.attributes).is_truthy() =>
+                {
+                    pos = next
+                }
+                _ => return next + 1,
+            }
+        }
+        self.script.ctx.len()
+    }
+
+    /// Position following the @endif of the conditional branch at `from`
+    fn conditional_end(&self, from: usize) -> usize {
+        let mut pos = from;
+        while let Some(next) = self.script.next_branch(pos) {
+            if let ScriptContext::Directive(ScriptDirective::EndIf) = self.script.ctx[next] {
+                return next + 1;
+            }
+            pos = next;
+        }
+        self.script.ctx.len()
+    }
+
+    pub fn next_until<P>(&mut self, predicate: P) -> Result<Option<&ScriptContext>, EngineError>
     where
         P: Fn(&ScriptContext) -> bool,
     {
@@ -270,152 +849,552 @@ impl Engine {
             if predicate(context) {
                 break;
             }
-            self.next(false)?;
+            self.next(None)?;
         }
         Ok(self.current())
     }
 
-    pub fn next_until_renderable(&mut self) -> Result<Option<&ScriptContext>, LoadImageError> {
-        while let Some(context) = self.current() {
-            match context {
-                ScriptContext::Dialogue(_) => break,
-                ScriptContext::Directive(directive) => {
-                    if let ScriptDirective::Jump(jump) = directive {
-                        if jump.choices.is_some() {
-                            break;
-                        }
-                    }
-                }
-            };
-            self.next(false)?;
+    pub fn next_until_renderable(&mut self) -> Result<Option<&ScriptContext>, EngineError> {
+        self.next_until(Self::is_renderable)
+    }
+
+    /// Advance past every remaining page of the current dialogue
+    fn next_dialogue(&mut self) -> Result<(), EngineError> {
+        let iscript = self.iscript;
+        while self.iscript == iscript && matches!(self.current(), Some(ScriptContext::Dialogue(_)))
+        {
+            self.next(None)?;
+        }
+        Ok(())
+    }
+
+    /// Advance past the current dialogue and every following dialogue already read,
+    /// stopping at the first unread dialogue, choice or the end of the story
+    pub fn skip_read(&mut self) -> Result<Option<&ScriptContext>, EngineError> {
+        self.next_dialogue()?;
+        while let Some(ScriptContext::Dialogue(_)) = self.next_until_renderable()? {
+            if !self.is_read() {
+                break;
+            }
+            self.next_dialogue()?;
         }
         Ok(self.current())
     }
 
+    /// What should be displayed at the current context, or `None` if it is not renderable
+    pub fn frame(&self) -> Option<Frame> {
+        let content = match self.current()? {
+            ScriptContext::Dialogue(dialogue) => {
+                let speaker = &dialogue.character_name;
+                let (page, _) = self.page()?;
+                let runs = self.pages[page].clone();
+                FrameContent::Dialogue {
+                    speaker: speaker.clone(),
+                    text: plain_text(&runs),
+                    runs,
+                    text_color: character_color(&self.attributes, speaker, "text_color")
+                        .unwrap_or(DEFAULT_TEXT_COLOR),
+                    dialogue_color: character_color(&self.attributes, speaker, "dialogue_color")
+                        .unwrap_or(DEFAULT_DIALOGUE_COLOR),
+                    font: character_font(&self.attributes, speaker),
+                }
+            }
+            ScriptContext::Directive(ScriptDirective::Jump(jump)) => FrameContent::Choice {
+                choices: jump
+                    .choices
+                    .as_ref()?
+                    .iter()
+                    .map(|choice| choice.text.clone())
+                    .collect(),
+            },
+            ScriptContext::Directive(_) => return None,
+        };
+        let sprites = match content {
+            FrameContent::Dialogue { .. } => self
+                .sprites
+                .iter()
+                .filter(|sprite| sprite.show)
+                .filter_map(|sprite| {
+                    let path = sprite.sprite_path.clone()?;
+                    let scale = self
+                        .attributes
+                        .get_path(&format!("sprite.{}.scale", sprite.name))
+                        .and_then(AttributeValue::as_value)
+                        .and_then(|scale| match scale.parse::<f64>() {
+                            Ok(scale) => Some(scale),
+                            Err(_) => {
+                                warn!(
+                                    "scale of {} cannot be parsed as a float. Ignoring scaling",
+                                    sprite.name
+                                );
+                                None
+                            }
+                        });
+                    let image = match scale {
+                        Some(scale) => self.assets.get_scaled(self.loader.as_ref(), &path, scale),
+                        None => self.assets.get(self.loader.as_ref(), &path),
+                    };
+                    let image = match image {
+                        Ok(image) => image,
+                        Err(e) => {
+                            warn!("Unable to load sprite {} from {}: {}", sprite.name, path, e);
+                            return None;
+                        }
+                    };
+                    Some(FrameSprite {
+                        name: sprite.name.clone(),
+                        path,
+                        x: sprite.x?,
+                        y: sprite.y?,
+                        scale,
+                        image,
+                    })
+                })
+                .collect(),
+            FrameContent::Choice { .. } => Vec::new(),
+        };
+
+        Some(Frame {
+            bg_path: self.bg_path.clone(),
+            bg: self.bg.clone(),
+            sprites,
+            content,
+        })
+    }
+
     pub fn render(&self) {
         self.render_to(&format!("{}_{}.png", self.script.name, self.iscript));
     }
 
     pub fn render_to(&self, path: &str) {
-        if let Some(current) = self.current() {
-            if let Some(image) = match current {
-                ScriptContext::Dialogue(dialogue) => Some(
-                    self.scene.draw_dialogue(
-                        self.bg_path
-                            .as_ref()
-                            .and_then(|bg_path| self.cached_bgs.get(bg_path)),
-                        &self.sprites,
-                        &dialogue.character_name,
-                        &dialogue
-                            .dialogues
-                            .iter()
-                            .fold(String::new(), |a, b| a + " " + b),
-                        &self.attributes,
-                    ),
-                ),
-                ScriptContext::Directive(directive) => match directive {
-                    ScriptDirective::Jump(jump) => {
-                        if let Some((a, b)) = &jump.choices {
-                            Some(
-                                self.scene.draw_choice(
-                                    self.bg_path
-                                        .as_ref()
-                                        .and_then(|bg_path| self.cached_bgs.get(bg_path)),
-                                    &(a.as_str(), b.as_str()),
-                                ),
-                            )
-                        } else {
-                            None
-                        }
-                    }
-                    _ => None,
-                },
-            } {
-                image.save(path).expect("Unable to save image");
-            }
+        if let Some(frame) = self.frame() {
+            self.scene
+                .draw_frame(&frame)
+                .save(path)
+                .expect("Unable to save image");
         }
     }
 
+    /// Render to path, copying the previous render of an identical frame if caching is enabled
     pub fn cache_render_to(&mut self, path: &str) {
-        if let Some(current) = self.current() {
-            let mut render_hash = None;
-            if let Some(image) = match current {
-                ScriptContext::Dialogue(dialogue) => {
-                    let bg = self
-                        .bg_path
-                        .as_ref()
-                        .and_then(|bg_path| self.cached_bgs.get(bg_path));
-                    let sprites = &self.sprites;
-                    let character_name = &dialogue.character_name;
-                    let dialogue = &dialogue
-                        .dialogues
-                        .iter()
-                        .fold(String::new(), |a, b| a + " " + b);
-                    let attributes = &self.attributes;
-
-                    if let Some(cache) = &self.cache {
-                        let hash = self.scene.dialogue_hash(
-                            bg,
-                            sprites,
-                            character_name,
-                            dialogue,
-                            attributes,
-                        );
-                        render_hash = Some(hash);
-                        if let Some(cache_path) = cache.iter().find(|h| {
-                            h.file_name()
-                                .unwrap()
-                                .to_str()
-                                .unwrap()
-                                .starts_with(hash.to_string().as_str())
-                        }) {
-                            debug!("Using cached render {:?} for {}", cache_path, path);
-                            fs::copy(cache_path, path).unwrap();
-                            return;
-                        }
-                    }
-                    Some(self.scene.draw_dialogue(
-                        bg,
-                        sprites,
-                        character_name,
-                        dialogue,
-                        attributes,
-                    ))
-                }
-                ScriptContext::Directive(directive) => match directive {
-                    ScriptDirective::Jump(jump) => {
-                        if let Some((a, b)) = &jump.choices {
-                            Some(
-                                self.scene.draw_choice(
-                                    self.bg_path
-                                        .as_ref()
-                                        .and_then(|bg_path| self.cached_bgs.get(bg_path)),
-                                    &(a.as_str(), b.as_str()),
-                                ),
-                            )
-                        } else {
-                            None
-                        }
-                    }
-                    _ => None,
-                },
-            } {
-                image.save(path).expect("Unable to save image");
-                if let Some(hash) = render_hash {
-                    image
-                        .save(&format!("resources/render/.cache/{}.png", hash))
-                        .unwrap();
-                }
+        let frame = match self.frame() {
+            Some(frame) => frame,
+            None => return,
+        };
+        let hash = self.cache.as_ref().map(|_| self.scene.frame_hash(&frame));
+        if let (Some((_, cache)), Some(hash)) = (&self.cache, hash) {
+            if let Some(cache_path) = cache.iter().find(|h| {
+                h.file_name()
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .starts_with(hash.to_string().as_str())
+            }) {
+                debug!("Using cached render {:?} for {}", cache_path, path);
+                fs::copy(cache_path, path).unwrap();
+                return;
             }
         }
+
+        let image = self.scene.draw_frame(&frame);
+        image.save(path).expect("Unable to save image");
+        if let (Some((dir, _)), Some(hash)) = (&self.cache, hash) {
+            image.save(dir.join(format!("{}.png", hash))).unwrap();
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::engine::{AttributeDirective, Directive};
+    use std::{collections::HashMap, sync::Arc};
+
+    use image::{DynamicImage, GenericImageView};
+    use rusttype::{Font, Scale};
+
+    use crate::{
+        engine::{
+            markup::{Run, Style},
+            AttributeDirective, CustomArgs, CustomHandler, Directive, EngineError, FrameContent,
+            FrameSprite, MemoryLoader, ReadLog, SaveData, SaveError, ScriptContext,
+            ScriptDirective,
+        },
+        img::{
+            fixtures::{images, png},
+            Assets,
+        },
+        Overflow, Scene, Script, Size,
+    };
+
+    use super::{AttributeValue, Attributes, Engine};
+
+    fn scene() -> Scene {
+        let font_data = include_bytes!("../../resources/fonts/cour.ttf");
+        Scene {
+            font: Font::try_from_bytes(font_data as &[u8]).unwrap(),
+            fallback: Vec::new(),
+            fonts: HashMap::new(),
+            scale: Scale::uniform(24.0),
+            screen: Size {
+                xmin: 0,
+                ymin: 0,
+                ymax: 480,
+                xmax: 640,
+            },
+            sprite: Size {
+                xmin: 0,
+                ymin: 0,
+                ymax: 0,
+                xmax: 0,
+            },
+            text: Size {
+                xmin: 20,
+                xmax: 620,
+                ymin: 340,
+                ymax: 470,
+            },
+            overflow: Overflow::Paginate,
+        }
+    }
+
+    fn dialogues(engine: &mut Engine, choices: &[usize]) -> Vec<String> {
+        let mut choices = choices.iter();
+        let mut shown = Vec::new();
+        while let Some(ctx) = engine.next_until_renderable().unwrap().cloned() {
+            let choice = match ctx {
+                ScriptContext::Dialogue(dialogue) => {
+                    shown.push(engine.interpolate(&dialogue.dialogues.join(" ")));
+                    None
+                }
+                _ => choices.next().copied(),
+            };
+            engine.next(choice).unwrap();
+        }
+        shown
+    }
+
+    #[test]
+    fn flow() {
+        let mut loader = MemoryLoader::new();
+        loader.insert(
+            "b.txt",
+            "@label(hall)\n@if(score > 1)\n[]\nhigh {score}\n@else\n[]\nlow\n@endif\n",
+        );
+        let script = Script::parse(
+            "a.txt",
+            "@set(score, 1)\n@jump(Up, #up, Stay, , Hall, b.txt#hall)\n\
+             @jump(b.txt)\n@label(up)\n@set(score, score + 1)\n@jump(b.txt#hall)\n",
+        )
+        .unwrap();
+
+        for (choice, expected) in [(0, "high 2"), (1, "low"), (2, "low")] {
+            let mut engine = Engine::from_script(script.clone(), scene());
+            engine.set_loader(loader.clone());
+            assert_eq!(dialogues(&mut engine, &[choice]), [expected]);
+        }
+    }
+
+    #[test]
+    fn back() {
+        let mut loader = MemoryLoader::new();
+        loader.insert("b.txt", "@sprite(john, hide)\n[]\nin b {x}\n");
+        let script = Script::parse(
+            "a.txt",
+            "@sprite(john, john.png, 320, 240, show)\n[]\none\n@set(x, 1)\n\
+             @jump(Go, b.txt, Stay, )\n",
+        )
+        .unwrap();
+        let mut engine = Engine::from_script(script, scene());
+        engine.set_loader(loader);
+        assert!(engine.back().is_none());
+
+        engine.next_until_renderable().unwrap();
+        engine.next(None).unwrap();
+        engine.next_until_renderable().unwrap();
+        engine.next(Some(0)).unwrap();
+        engine.next_until_renderable().unwrap();
+        assert_eq!(engine.script.name, "b.txt");
+        assert!(!engine.sprites[0].show);
+        assert_eq!(
+            engine
+                .backlog()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["one", "> Go"]
+        );
+        assert_eq!(engine.backlog().nth(1).unwrap().index, 3);
+
+        // Back to the choice, in a.txt with john still shown
+        assert!(matches!(
+            engine.back(),
+            Some(ScriptContext::Directive(ScriptDirective::Jump(_)))
+        ));
+        assert_eq!((engine.script.name.as_str(), engine.iscript), ("a.txt", 3));
+        assert!(engine.sprites[0].show);
+        assert_eq!(engine.interpolate("{x}"), "1");
+        assert_eq!(engine.backlog().count(), 1);
+
+        assert!(matches!(engine.back(), Some(ScriptContext::Dialogue(_))));
+        assert_eq!(engine.interpolate("{x}"), "{x}");
+        assert!(!engine.has_history());
+
+        // Replaying after going back takes the same path
+        engine.next(None).unwrap();
+        engine.next_until_renderable().unwrap();
+        engine.next(Some(1)).unwrap();
+        assert!(engine.next_until_renderable().unwrap().is_none());
+        assert_eq!(engine.script.name, "a.txt");
+    }
+
+    struct Wait;
+
+    impl CustomHandler for Wait {
+        type Args = u32;
+
+        fn parse(&self, args: CustomArgs) -> Result<u32, String> {
+            args.get(0, "milliseconds")
+        }
+
+        fn run(&self, _: u32) {}
+    }
+
+    #[test]
+    fn failed_jump() {
+        let mut loader = MemoryLoader::new();
+        loader
+            .insert("b.txt", "@custom(wait(soon))\n[]\nin b\n")
+            .insert("d.txt", "[]\nin d\n");
+        let script = Script::parse(
+            "a.txt",
+            "[]\none\n@jump(Wait, b.txt, Missing, c.txt, Hall, d.txt#hall)\n",
+        )
+        .unwrap();
+        let mut engine = Engine::from_script(script, scene());
+        engine.set_loader(loader);
+        engine.register_custom("wait", Wait).unwrap();
+
+        engine.next_until_renderable().unwrap();
+        engine.next(None).unwrap();
+        engine.next_until_renderable().unwrap();
+        for choice in [0, 1, 2] {
+            match engine.next(Some(choice)) {
+                Err(EngineError::Script(..)) if choice < 2 => {}
+                Err(EngineError::MissingLabel(label, script)) if choice == 2 => {
+                    assert_eq!((label.as_str(), script.as_str()), ("hall", "d.txt"))
+                }
+                result => panic!("unexpected result {:?}", result.map(|_| ())),
+            }
+            // Still at the choice, with the failed pick left out of the backlog
+            assert_eq!((engine.script.name.as_str(), engine.iscript), ("a.txt", 1));
+            assert_eq!(engine.backlog().count(), 1);
+        }
+        assert!(matches!(engine.back(), Some(ScriptContext::Dialogue(_))));
+    }
+
+    #[test]
+    fn save_and_restore() {
+        let mut loader = MemoryLoader::new();
+        loader
+            .insert(
+                "a.txt",
+                "@set(route.score, 2)\n@sprite(john, john.png, 320, 240, show)\n\
+                 @jump(b.txt)\n",
+            )
+            .insert("b.txt", "[]\nfirst\n[]\nscore {route.score}\n");
+        let mut engine = Engine::from_script(Script::load("a.txt", &loader).unwrap(), scene());
+        engine.set_loader(loader.clone());
+        engine.next_until_renderable().unwrap();
+        engine.next(None).unwrap();
+
+        let save = SaveData::from_json(&engine.save().to_json()).unwrap();
+        assert_eq!((save.script_path.as_str(), save.iscript), ("b.txt", 1));
+        let mut restored = Engine::restore_with(save, scene(), loader).unwrap();
+        assert_eq!(restored.sprites[0].sprite_path.as_deref(), Some("john.png"));
+        assert_eq!(dialogues(&mut restored, &[]), ["score 2"]);
 
-    use super::Attributes;
+        let future = engine
+            .save()
+            .to_json()
+            .replace("\"version\": 1", "\"version\": 99");
+        assert!(matches!(
+            SaveData::from_json(&future),
+            Err(SaveError::UnsupportedVersion(99, 1))
+        ));
+    }
+
+    #[test]
+    fn frame() {
+        let script = Script::parse(
+            "a.txt",
+            "@sprite(john, john.png, 320, 240, show)\n@sprite(mary, mary.png, 100, 240, show)\n\
+             @sprite(mary, hide)\n@attr(sprite.john.scale, 0.5)\n\
+             @sprite(ghost, ghost.png, 0, 0, show)\n\
+             @attr(character.John.text_color, ff0000ff)\n@attr(character.John.font, robot)\n\
+             @set(x, 2)\n[John]\nx is {b}{x}{/b}\n\
+             @jump(Up, , Down, )\n",
+        )
+        .unwrap();
+        let mut scene = scene();
+        scene.fonts.insert("robot".to_string(), scene.font.clone());
+        let mut engine = Engine::from_script(script, scene);
+        engine.set_loader(images(&[("john.png", 8)]));
+        engine.set_assets(Assets::new(1024));
+        assert!(engine.frame().is_none());
+
+        // Sprites that cannot be loaded are left out
+        engine.next_until_renderable().unwrap();
+        let frame = engine.frame().unwrap();
+        assert_eq!(
+            frame.sprites,
+            [FrameSprite {
+                name: "john".to_string(),
+                path: "john.png".to_string(),
+                x: 320,
+                y: 240,
+                scale: Some(0.5),
+                image: Arc::new(DynamicImage::new_rgba8(4, 4)),
+            }]
+        );
+        assert_eq!(
+            frame.content,
+            FrameContent::Dialogue {
+                speaker: "John".to_string(),
+                text: "x is 2".to_string(),
+                runs: vec![
+                    Run::plain("x is "),
+                    Run {
+                        text: "2".to_string(),
+                        style: Style {
+                            bold: true,
+                            ..Style::default()
+                        },
+                    },
+                ],
+                text_color: [255, 0, 0, 255],
+                dialogue_color: [0, 0, 0, 127],
+                font: Some("robot".to_string()),
+            }
+        );
+        assert_eq!(frame.describe(), "John: x is 2");
+
+        engine.next(None).unwrap();
+        let frame = engine.frame().unwrap();
+        assert_eq!(frame.choices().unwrap(), ["Up", "Down"]);
+        assert!(frame.sprites.is_empty());
+    }
+
+    #[test]
+    fn pages() {
+        let text = format!("[John]\n{}\n[John]\nafter\n", "word ".repeat(200).trim());
+        let script = Script::parse("a.txt", &text).unwrap();
+        let mut engine = Engine::from_script(script.clone(), scene());
+        engine.next_until_renderable().unwrap();
+        let (_, pages) = engine.page().unwrap();
+        assert!(pages > 1);
+
+        // Every page is its own frame, together showing the whole dialogue
+        let mut shown = Vec::new();
+        for page in 0..pages {
+            assert_eq!(engine.page(), Some((page, pages)));
+            shown.push(engine.frame().unwrap().describe().replace("John: ", ""));
+            engine.next(None).unwrap();
+        }
+        assert_eq!(shown.join(" ").split_whitespace().count(), 200);
+        assert_eq!(engine.frame().unwrap().describe(), "John: after");
+        assert_eq!(engine.backlog().count(), 1);
+
+        engine.back();
+        assert_eq!(engine.page(), Some((pages - 1, pages)));
+        engine.back();
+        assert_eq!(engine.page(), Some((pages - 2, pages)));
+        let save = SaveData::from_json(&engine.save().to_json()).unwrap();
+        let mut loader = MemoryLoader::new();
+        loader.insert("a.txt", &text);
+        let restored = Engine::restore_with(save, scene(), loader).unwrap();
+        assert_eq!(restored.page(), Some((pages - 2, pages)));
+
+        // Skipping skips every page
+        engine.skip_read().unwrap();
+        assert_eq!(engine.frame().unwrap().describe(), "John: after");
+
+        let mut shrink = Engine::from_script(
+            script,
+            Scene {
+                overflow: Overflow::Shrink,
+                ..scene()
+            },
+        );
+        shrink.next_until_renderable().unwrap();
+        assert_eq!(shrink.page(), Some((0, 1)));
+    }
+
+    #[test]
+    fn skip_read() {
+        let script = Script::parse(
+            "a.txt",
+            "[]\none\n[]\ntwo\n@jump(Left, #left, Right, #right)\n@label(left)\n[]\nleft\n\
+             @jump(#end)\n@label(right)\n[]\nright\n@label(end)\n[]\nend\n",
+        )
+        .unwrap();
+        let mut engine = Engine::from_script(script.clone(), scene());
+        assert_eq!(dialogues(&mut engine, &[0]), ["one", "two", "left", "end"]);
+        let read = ReadLog::from_json(&engine.read_log().to_json()).unwrap();
+        assert_eq!(read.len(), 4);
+
+        // A new run stops at the choice, then at the first unread dialogue of the other route
+        let mut engine = Engine::from_script(script, scene());
+        engine.set_read_log(read);
+        engine.next_until_renderable().unwrap();
+        assert!(engine.is_read());
+        assert!(matches!(
+            engine.skip_read().unwrap(),
+            Some(ScriptContext::Directive(ScriptDirective::Jump(_)))
+        ));
+        assert!(matches!(
+            engine.skip_read().unwrap(),
+            Some(ScriptContext::Directive(ScriptDirective::Jump(_)))
+        ));
+        engine.next(Some(1)).unwrap();
+        engine.skip_read().unwrap();
+        assert_eq!(engine.frame().unwrap().describe(), "right");
+        assert!(!engine.is_read());
+
+        // Skipping past the unread dialogue marks it read on the way
+        assert!(engine.skip_read().unwrap().is_none());
+        assert_eq!(engine.backlog().count(), 5);
+        assert_eq!(engine.read_log().len(), 5);
+
+        // Dialogues moved by edits to their script stay read
+        let edited = Script::parse("a.txt", "[]\nnew\n[]\none\n").unwrap();
+        let read = engine.read_log().clone();
+        let mut engine = Engine::from_script(edited, scene());
+        engine.set_read_log(read);
+        engine.next_until_renderable().unwrap();
+        assert!(!engine.is_read());
+        engine.next(None).unwrap();
+        assert!(engine.is_read());
+    }
+
+    #[test]
+    fn game_root() {
+        let png = png(4, 2);
+        let mut loader = MemoryLoader::new();
+        loader
+            .insert(
+                "story/a.txt",
+                "@sprite(john, ./john.png, 320, 240, show)\n@jump(./routes/b.txt)\n",
+            )
+            .insert("story/routes/b.txt", "@loadbg(../../bg.png)\n[]\nin b\n")
+            .insert_file("bg.png", png.clone())
+            .insert_file("story/john.png", png);
+
+        let mut engine = Engine::load("story/a.txt", scene(), loader).unwrap();
+        engine.next_until_renderable().unwrap();
+        assert_eq!(engine.script.name, "story/routes/b.txt");
+        let frame = engine.frame().unwrap();
+        assert_eq!(frame.bg_path.as_deref(), Some("bg.png"));
+        assert_eq!(frame.sprites[0].path, "story/john.png");
+        assert_eq!(frame.sprites[0].image.dimensions(), (4, 2));
+    }
 
     #[test]
     fn foo() {
@@ -427,4 +1406,33 @@ mod test {
         attrs.add_attribute(&AttributeDirective::from_context("d, 2").unwrap());
         println!("{:#?}", attrs.get_path("a.b.c.d"));
     }
+
+    #[test]
+    fn attribute_conflict() {
+        let mut attrs = Attributes::new();
+        attrs.add_attribute(&AttributeDirective::from_context("a.b, 1").unwrap());
+        attrs.add_attribute(&AttributeDirective::from_context("a.b.c, 2").unwrap());
+        assert_eq!(
+            attrs.get_path("a.b.c").and_then(AttributeValue::as_value),
+            Some("2")
+        );
+
+        // And the other way around
+        attrs.add_attribute(&AttributeDirective::from_context("a, 3").unwrap());
+        assert_eq!(
+            attrs.get_path("a").and_then(AttributeValue::as_value),
+            Some("3")
+        );
+    }
+
+    #[test]
+    fn interpolate() {
+        let mut attrs = Attributes::new();
+        attrs.add_attribute(&AttributeDirective::from_context("player.name, Alice").unwrap());
+        assert_eq!(
+            attrs.interpolate("Hello, {player.name}! {{player.name}} {b}bold{/b} { }"),
+            "Hello, Alice! {player.name} {b}bold{/b} { }"
+        );
+        assert_eq!(attrs.interpolate("{{{player.name}}}"), "{Alice}");
+    }
 }
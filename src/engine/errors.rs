@@ -1,12 +1,27 @@
-use std::fmt::Debug;
+use std::{
+    fmt::{self, Debug},
+    io,
+    ops::Range,
+};
 
 use thiserror::Error;
 
-#[derive(Debug)]
-pub struct SyntaxError {
+use crate::img::error::LoadImageError;
+
+/// Location of a token within a script file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Span {
     pub file: String,
     pub line: usize,
-    pub character: usize,
+    /// Column of the token's first character, counting characters from 1
+    pub column: usize,
+    /// Byte range of the token within the file
+    pub bytes: Range<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SyntaxError {
+    pub span: Span,
     pub why: String,
 }
 
@@ -23,8 +38,69 @@ pub enum ParseError {
     DirectiveError(&'static str, String),
     #[error("Cannot open file \"{0}\"")]
     NoFileExists(String),
+    #[error("Cannot read file \"{0}\" because {1}")]
+    IoError(String, io::Error),
     #[error("Error on file \"{0}\" line {1} character {2}: {3}")]
     SyntaxError(String, usize, usize, String),
+    #[error("{}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n"))]
+    Diagnostics(Vec<SyntaxError>),
     #[error("Image error on image \"{0}\" because {1}")]
     ImageError(String, String),
+    #[error("Invalid {0} in config: {1}")]
+    InvalidConfig(String, String),
+}
+
+#[derive(Error, Debug)]
+pub enum SaveError {
+    #[error("Cannot access save file \"{0}\" because {1}")]
+    IoError(String, io::Error),
+    #[error("Malformed save: {0}")]
+    Malformed(serde_json::Error),
+    #[error("Save has no version")]
+    MissingVersion,
+    #[error("Save version {0} is newer than the supported version {1}")]
+    UnsupportedVersion(u64, u32),
+    #[error(transparent)]
+    ParseError(#[from] ParseError),
+    #[error("Cannot load saved background: {0}")]
+    Image(#[from] LoadImageError),
+    #[error("Save position {0} is past the end of {1}")]
+    OutOfBounds(usize, String),
+}
+
+/// Why the engine cannot continue the story
+#[derive(Error, Debug)]
+pub enum EngineError {
+    #[error("Cannot load background: {0}")]
+    Image(#[from] LoadImageError),
+    #[error("Cannot load script {0}: {1}")]
+    Script(String, ParseError),
+    #[error("Cannot find label {0} in {1}")]
+    MissingLabel(String, String),
+    #[error("Cannot run custom directive {0}: {1}")]
+    Custom(String, String),
+}
+
+#[derive(Error, Debug)]
+pub enum PackageError {
+    #[error("Cannot access package \"{0}\" because {1}")]
+    IoError(String, io::Error),
+    #[error("Malformed package: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("Malformed package manifest: {0}")]
+    Malformed(serde_json::Error),
+    #[error("Package version {0} is newer than the supported version {1}")]
+    UnsupportedVersion(u64, u32),
+    #[error("Package is missing \"{0}\" listed in its manifest")]
+    MissingFile(String),
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Error on file \"{}\" line {} character {}: {}",
+            self.span.file, self.span.line, self.span.column, self.why
+        )
+    }
 }
@@ -0,0 +1,359 @@
+use std::fmt;
+
+use super::{
+    engine::{Attributes, Number},
+    ParseError,
+};
+
+/// Expression used by `@set` and conditional directives
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    Variable(String),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, BinaryOp, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Number(Number),
+    Bool(bool),
+    Str(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(Number),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+    Open,
+    Close,
+}
+
+const OPERATORS: [&str; 13] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "+", "-", "!", "(", ")",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        if c == '"' {
+            let mut s = String::new();
+            let mut chars = rest[1..].char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, '"')) => break i + 2,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c)) => s.push(c),
+                        None => return Err("unterminated string".into()),
+                    },
+                    Some((_, c)) => s.push(c),
+                    None => return Err("unterminated string".into()),
+                }
+            };
+            tokens.push(Token::Str(s));
+            rest = &rest[end..];
+        } else if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(
+                Number::parse(&rest[..end])
+                    .ok_or_else(|| format!("invalid number {}", &rest[..end]))?,
+            ));
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(match *op {
+                "(" => Token::Open,
+                ")" => Token::Close,
+                op => Token::Op(op),
+            });
+            rest = &rest[op.len()..];
+        } else {
+            return Err(format!("unexpected character {}", c));
+        }
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_op(&self, ops: &[&'static str]) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) if ops.contains(op) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn binary<F>(&mut self, ops: &[&'static str], next: F) -> Result<Expr, String>
+    where
+        F: Fn(&mut Self) -> Result<Expr, String>,
+    {
+        let mut lhs = next(self)?;
+        while let Some(op) = self.peek_op(ops) {
+            self.pos += 1;
+            let rhs = next(self)?;
+            lhs = Expr::Binary(Box::new(lhs), BinaryOp::from_str(op), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        self.binary(&["||"], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        self.binary(&["&&"], Self::not)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.peek_op(&["!"]).is_some() {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let lhs = self.sum()?;
+        match self.peek_op(&["==", "!=", "<", "<=", ">", ">="]) {
+            Some(op) => {
+                self.pos += 1;
+                let rhs = self.sum()?;
+                Ok(Expr::Binary(
+                    Box::new(lhs),
+                    BinaryOp::from_str(op),
+                    Box::new(rhs),
+                ))
+            }
+            None => Ok(lhs),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        self.binary(&["+", "-"], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.peek_op(&["-"]).is_some() {
+            self.pos += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| "unexpected end of expression".to_string())?;
+        self.pos += 1;
+
+        Ok(match token {
+            Token::Number(n) => Expr::Literal(Value::Number(n)),
+            Token::Str(s) => Expr::Literal(Value::Str(s)),
+            Token::Ident(ident) => match ident.as_str() {
+                "true" => Expr::Literal(Value::Bool(true)),
+                "false" => Expr::Literal(Value::Bool(false)),
+                _ => Expr::Variable(ident),
+            },
+            Token::Open => {
+                let expr = self.or()?;
+                if self.tokens.get(self.pos) != Some(&Token::Close) {
+                    return Err("expected closing )".into());
+                }
+                self.pos += 1;
+                expr
+            }
+            Token::Close => return Err("unexpected )".into()),
+            Token::Op(op) => return Err(format!("unexpected operator {}", op)),
+        })
+    }
+}
+
+impl BinaryOp {
+    fn from_str(op: &str) -> Self {
+        match op {
+            "+" => Self::Add,
+            "-" => Self::Sub,
+            "==" => Self::Eq,
+            "!=" => Self::Ne,
+            "<" => Self::Lt,
+            "<=" => Self::Le,
+            ">" => Self::Gt,
+            ">=" => Self::Ge,
+            "&&" => Self::And,
+            "||" => Self::Or,
+            _ => unreachable!("unknown operator {}", op),
+        }
+    }
+}
+
+impl Expr {
+    /// Parse an expression, reporting errors on behalf of `directive`
+    pub fn parse(directive: &'static str, source: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(source).map_err(|e| ParseError::DirectiveError(directive, e))?,
+            pos: 0,
+        };
+        let expr = parser
+            .or()
+            .map_err(|e| ParseError::DirectiveError(directive, e))?;
+        if parser.pos != parser.tokens.len() {
+            return Err(ParseError::DirectiveError(
+                directive,
+                format!("unexpected trailing input in {}", source.trim()),
+            ));
+        }
+        Ok(expr)
+    }
+
+    /// Evaluate the expression, unset variables evaluate to 0
+    pub fn eval(&self, attributes: &Attributes) -> Value {
+        match self {
+            Self::Literal(value) => value.clone(),
+            Self::Variable(path) => attributes
+                .get_path(path)
+                .and_then(|v| v.as_value())
+                .map(Value::parse)
+                .unwrap_or(Value::Number(Number::PosInt(0))),
+            Self::Not(expr) => Value::Bool(!expr.eval(attributes).is_truthy()),
+            Self::Neg(expr) => match expr.eval(attributes) {
+                Value::Number(n) => Value::Number(n.neg()),
+                value => value,
+            },
+            Self::Binary(lhs, op, rhs) => {
+                let lhs = lhs.eval(attributes);
+                match op {
+                    BinaryOp::And if !lhs.is_truthy() => return Value::Bool(false),
+                    BinaryOp::Or if lhs.is_truthy() => return Value::Bool(true),
+                    _ => {}
+                }
+                let rhs = rhs.eval(attributes);
+
+                match (op, &lhs, &rhs) {
+                    (BinaryOp::And | BinaryOp::Or, _, _) => Value::Bool(rhs.is_truthy()),
+                    (BinaryOp::Add, Value::Number(a), Value::Number(b)) => Value::Number(a.add(b)),
+                    (BinaryOp::Sub, Value::Number(a), Value::Number(b)) => {
+                        Value::Number(a.add(&b.neg()))
+                    }
+                    (BinaryOp::Add, _, _) => Value::Str(format!("{}{}", lhs, rhs)),
+                    (BinaryOp::Sub, _, _) => Value::Number(Number::PosInt(0)),
+                    (BinaryOp::Eq, _, _) => Value::Bool(lhs.equals(&rhs)),
+                    (BinaryOp::Ne, _, _) => Value::Bool(!lhs.equals(&rhs)),
+                    (op, _, _) => Value::Bool(match lhs.compare(&rhs) {
+                        Some(ordering) => match op {
+                            BinaryOp::Lt => ordering.is_lt(),
+                            BinaryOp::Le => ordering.is_le(),
+                            BinaryOp::Gt => ordering.is_gt(),
+                            _ => ordering.is_ge(),
+                        },
+                        None => false,
+                    }),
+                }
+            }
+        }
+    }
+}
+
+impl Value {
+    /// Interpret a stored attribute value
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "true" => Self::Bool(true),
+            "false" => Self::Bool(false),
+            _ => Number::parse(s)
+                .map(Self::Number)
+                .unwrap_or_else(|| Self::Str(s.to_string())),
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Self::Number(n) => n.as_f64_lossy() != 0.,
+            Self::Bool(b) => *b,
+            Self::Str(s) => !s.is_empty(),
+        }
+    }
+
+    fn equals(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Number(a), Self::Number(b)) => a.as_f64_lossy() == b.as_f64_lossy(),
+            (a, b) => a == b,
+        }
+    }
+
+    fn compare(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        match (self, other) {
+            (Self::Number(a), Self::Number(b)) => a.as_f64_lossy().partial_cmp(&b.as_f64_lossy()),
+            (Self::Str(a), Self::Str(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{}", n),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Str(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::{engine::Attributes, AttributeDirective, Directive};
+
+    use super::{Expr, Value};
+
+    #[test]
+    fn eval() {
+        let mut attrs = Attributes::new();
+        attrs.add_attribute(&AttributeDirective::from_context("route.score, 3").unwrap());
+        attrs.add_attribute(&AttributeDirective::from_context("met_alice, true").unwrap());
+        attrs.add_attribute(&AttributeDirective::from_context("name, Bob").unwrap());
+
+        let eval = |s: &str| Expr::parse("if", s).unwrap().eval(&attrs);
+        assert_eq!(eval("route.score + 2").to_string(), "5");
+        assert_eq!(eval("1 - 2.5").to_string(), "-1.5");
+        assert_eq!(eval("route.score >= 3 && met_alice"), Value::Bool(true));
+        assert_eq!(eval("!(met_alice || unset)"), Value::Bool(false));
+        assert_eq!(eval("name == \"Bob\""), Value::Bool(true));
+        assert_eq!(eval("unset == 0"), Value::Bool(true));
+        assert!(Expr::parse("if", "score >").is_err());
+        assert!(Expr::parse("if", "(score").is_err());
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::Arc;
+
+use image::DynamicImage;
+use log::warn;
+
+use super::{engine::Attributes, markup::Run};
+
+/// RGBA color
+pub type Color = [u8; 4];
+
+pub const DEFAULT_TEXT_COLOR: Color = [255, 255, 255, 255];
+pub const DEFAULT_DIALOGUE_COLOR: Color = [0, 0, 0, 255 / 2];
+
+/// Everything displayed at a renderable context, resolved from the engine's state
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub bg_path: Option<String>,
+    pub bg: Option<Arc<DynamicImage>>,
+    /// Shown sprites, drawn in order
+    pub sprites: Vec<FrameSprite>,
+    pub content: FrameContent,
+}
+
+/// Shown sprite with its attributes resolved
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameSprite {
+    pub name: String,
+    pub path: String,
+    /// Position of the center of the sprite
+    pub x: u32,
+    pub y: u32,
+    pub scale: Option<f64>,
+    /// Decoded sprite with its scale applied
+    pub image: Arc<DynamicImage>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrameContent {
+    Dialogue {
+        speaker: String,
+        /// Text with its attributes and variables substituted and its markup removed
+        text: String,
+        /// Text split into runs by its markup, drawn in order
+        runs: Vec<Run>,
+        text_color: Color,
+        dialogue_color: Color,
+        /// Name of the scene font the speaker talks in, the scene's own font if `None`
+        font: Option<String>,
+    },
+    /// Choices are shown over the background alone
+    Choice { choices: Vec<String> },
+}
+
+/// Parse a `RRGGBBAA` hexadecimal color
+pub(crate) fn parse_color(value: &str) -> Option<Color> {
+    u32::from_str_radix(value, 16).ok().map(u32::to_be_bytes)
+}
+
+/// Parse the `RRGGBBAA` hexadecimal color of `character.<name>.<key>`. `@attr` colors are
+/// checked when parsed, so only a color given by `@set` falls back to the default with a warning
+pub(crate) fn character_color(attributes: &Attributes, name: &str, key: &str) -> Option<Color> {
+    let value = attributes.get_path(&format!("character.{}.{}", name, key))?;
+    let color = value.as_value().and_then(parse_color);
+    if color.is_none() {
+        warn!(
+            "{} of {} is not a hexadecimal color, using the default",
+            key, name
+        );
+    }
+    color
+}
+
+/// Name of the font set by `character.<name>.font`
+pub(crate) fn character_font(attributes: &Attributes, name: &str) -> Option<String> {
+    let value = attributes.get_path(&format!("character.{}.font", name))?;
+    if value.as_value().is_none() {
+        warn!(
+            "font of {} is not a font name, using the scene's font",
+            name
+        );
+    }
+    value.as_value().map(str::to_string)
+}
+
+impl Frame {
+    pub fn speaker(&self) -> Option<&str> {
+        match &self.content {
+            FrameContent::Dialogue { speaker, .. } => Some(speaker),
+            FrameContent::Choice { .. } => None,
+        }
+    }
+
+    pub fn choices(&self) -> Option<&[String]> {
+        match &self.content {
+            FrameContent::Choice { choices } => Some(choices),
+            FrameContent::Dialogue { .. } => None,
+        }
+    }
+
+    /// Text of the frame for frontends without images, e.g. `Speaker: text` or numbered choices
+    pub fn describe(&self) -> String {
+        match &self.content {
+            FrameContent::Dialogue { speaker, text, .. } if speaker.trim().is_empty() => {
+                text.clone()
+            }
+            FrameContent::Dialogue { speaker, text, .. } => format!("{}: {}", speaker, text),
+            FrameContent::Choice { choices } => choices.iter().enumerate().fold(
+                "You are presented with the following choices:".to_string(),
+                |description, (i, choice)| format!("{}\n[{}] {}", description, i + 1, choice),
+            ),
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::collections::{HashSet, VecDeque};
+
+use serde::Serialize;
+
+use super::{
+    JumpDirective, LazilyLoadedScript, Script, ScriptContext, ScriptDirective, ScriptLoader,
+};
+
+/// Branching structure of a story, following every jump from its first script
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct StoryGraph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+/// Beginning of a script, or one of its labels
+#[derive(Clone, Debug, Serialize)]
+pub struct Node {
+    /// `path` for the beginning of a script and `path#label` for a label
+    pub id: String,
+    pub script: String,
+    pub label: Option<String>,
+    /// Why the script cannot be loaded, if it cannot
+    pub error: Option<String>,
+}
+
+/// Jump from the node containing it, labelled with the text of its choice
+#[derive(Clone, Debug, Serialize)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    /// Text of the choice taking the jump, or `None` for an unconditional jump or fall through.
+    /// Choices continuing the script label the edge to where the code after them leads
+    pub choice: Option<String>,
+}
+
+fn node_id(script: &str, label: Option<&str>) -> String {
+    match label {
+        Some(label) => format!("{}#{}", script, label),
+        None => script.to_string(),
+    }
+}
+
+/// Choices by which the code after a jump is reached, or a single `None` if it is reached by
+/// falling through
+fn ways(continuing: &[String]) -> Vec<Option<&str>> {
+    if continuing.is_empty() {
+        vec![None]
+    } else {
+        continuing
+            .iter()
+            .map(|choice| Some(choice.as_str()))
+            .collect()
+    }
+}
+
+/// Quote s as a DOT string
+fn quote(s: &str) -> String {
+    format!(
+        "\"{}\"",
+        s.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
+
+impl StoryGraph {
+    /// Walk every script reachable from start, loading them through loader
+    pub fn build(start: &str, loader: &dyn ScriptLoader) -> Self {
+        let mut graph = Self::default();
+        let mut visited = HashSet::from([start.to_string()]);
+        let mut queue = VecDeque::from([start.to_string()]);
+
+        while let Some(path) = queue.pop_front() {
+            let script = match Script::load(&path, loader) {
+                Ok(script) => script,
+                Err(e) => {
+                    graph.nodes.push(Node {
+                        id: node_id(&path, None),
+                        script: path,
+                        label: None,
+                        error: Some(e.to_string()),
+                    });
+                    continue;
+                }
+            };
+            for target in graph.add_script(&script) {
+                if visited.insert(target.clone()) {
+                    queue.push_back(target);
+                }
+            }
+        }
+
+        graph
+    }
+
+    /// Add the nodes and edges of script, returning the scripts it jumps to. Choices
+    /// continuing to the end of the script have no edge
+    fn add_script(&mut self, script: &Script) -> Vec<String> {
+        let mut targets = Vec::new();
+        let mut current = node_id(&script.name, None);
+        // Whether the previous context falls through to the next one
+        let mut falls_through = true;
+        // Text of the choices of the last jump continuing the script, which reach the next
+        // context instead of the previous one falling through
+        let mut continuing: Vec<String> = Vec::new();
+        self.nodes.push(Node {
+            id: current.clone(),
+            script: script.name.clone(),
+            label: None,
+            error: None,
+        });
+
+        for ctx in &script.ctx {
+            match ctx {
+                ScriptContext::Directive(ScriptDirective::Label(label)) => {
+                    let id = node_id(&script.name, Some(&label.name));
+                    self.nodes.push(Node {
+                        id: id.clone(),
+                        script: script.name.clone(),
+                        label: Some(label.name.clone()),
+                        error: None,
+                    });
+                    if falls_through {
+                        for choice in ways(&continuing) {
+                            self.edges.push(Edge {
+                                from: current.clone(),
+                                to: id.clone(),
+                                choice: choice.map(str::to_string),
+                            });
+                        }
+                    }
+                    current = id;
+                    falls_through = true;
+                    continuing.clear();
+                }
+                ScriptContext::Directive(ScriptDirective::Jump(jump)) => {
+                    targets.extend(self.add_jump(script, &current, jump, &continuing));
+                    continuing = jump
+                        .choices
+                        .iter()
+                        .flatten()
+                        .filter(|choice| choice.endpoint.is_none())
+                        .map(|choice| choice.text.clone())
+                        .collect();
+                    falls_through = !continuing.is_empty();
+                }
+                _ => falls_through = true,
+            }
+        }
+
+        targets
+    }
+
+    /// Add the edges of jump, reached by the choices continuing past the previous jump if any.
+    /// Returns the scripts it jumps to
+    fn add_jump(
+        &mut self,
+        script: &Script,
+        from: &str,
+        jump: &JumpDirective,
+        continuing: &[String],
+    ) -> Vec<String> {
+        let mut targets = Vec::new();
+        let mut edge = |endpoint: &LazilyLoadedScript, choice: Option<&str>| {
+            let path = if endpoint.is_local() {
+                script.name.clone()
+            } else {
+                targets.push(endpoint.script_path.clone());
+                endpoint.script_path.clone()
+            };
+            self.edges.push(Edge {
+                from: from.to_string(),
+                to: node_id(&path, endpoint.label.as_deref()),
+                choice: choice.map(str::to_string),
+            });
+        };
+
+        match (&jump.choices, &jump.endpoint) {
+            // Choices continuing the script get their edges from the code after the jump
+            (Some(choices), _) => {
+                for choice in choices {
+                    if let Some(endpoint) = &choice.endpoint {
+                        edge(endpoint, Some(&choice.text));
+                    }
+                }
+            }
+            (None, Some(endpoint)) => {
+                for choice in ways(continuing) {
+                    edge(endpoint, choice);
+                }
+            }
+            (None, None) => {}
+        }
+
+        targets
+    }
+
+    /// Render the graph in Graphviz DOT, grouping the nodes of each script
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph story {\n    node [shape=box];\n");
+        let mut scripts = Vec::new();
+        for node in &self.nodes {
+            if !scripts.contains(&&node.script) {
+                scripts.push(&node.script);
+            }
+        }
+
+        for (i, script) in scripts.iter().enumerate() {
+            dot += &format!(
+                "    subgraph cluster_{} {{\n        label={};\n",
+                i,
+                quote(script)
+            );
+            for node in self.nodes.iter().filter(|node| &&node.script == script) {
+                let label = match (&node.label, &node.error) {
+                    (_, Some(error)) => format!("{}\n{}", node.script, error),
+                    (Some(label), None) => format!("#{}", label),
+                    (None, None) => node.script.clone(),
+                };
+                dot += &format!(
+                    "        {} [label={}{}];\n",
+                    quote(&node.id),
+                    quote(&label),
+                    if node.error.is_some() {
+                        ", color=red"
+                    } else {
+                        ""
+                    }
+                );
+            }
+            dot += "    }\n";
+        }
+
+        for edge in &self.edges {
+            dot += &format!("    {} -> {}", quote(&edge.from), quote(&edge.to));
+            match &edge.choice {
+                Some(choice) => dot += &format!(" [label={}];\n", quote(choice)),
+                None => dot += ";\n",
+            }
+        }
+        dot += "}\n";
+        dot
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Unable to serialize graph")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::MemoryLoader;
+
+    use super::StoryGraph;
+
+    #[test]
+    fn build() {
+        let mut loader = MemoryLoader::new();
+        loader
+            .insert(
+                "a.txt",
+                "@jump(\"Go \\\"in\\\"\", b.txt#hall, Stay, )\n@label(end)\n@jump(c.txt)\n",
+            )
+            .insert("b.txt", "@label(hall)\n@jump(a.txt#end)\n");
+        let graph = StoryGraph::build("a.txt", &loader);
+
+        let edges = graph
+            .edges
+            .iter()
+            .map(|e| (e.from.as_str(), e.to.as_str(), e.choice.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            edges,
+            [
+                ("a.txt", "b.txt#hall", Some("Go \"in\"")),
+                ("a.txt", "a.txt#end", Some("Stay")),
+                ("a.txt#end", "c.txt", None),
+                ("b.txt", "b.txt#hall", None),
+                ("b.txt#hall", "a.txt#end", None),
+            ]
+        );
+        assert!(graph
+            .nodes
+            .iter()
+            .any(|n| n.id == "c.txt" && n.error.is_some()));
+
+        let dot = graph.to_dot();
+        assert!(dot.contains("\"a.txt\" -> \"b.txt#hall\" [label=\"Go \\\"in\\\"\"];"));
+        assert!(graph.to_json().contains("\"choice\": \"Stay\""));
+    }
+}
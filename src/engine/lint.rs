@@ -0,0 +1,285 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::Path,
+};
+
+use super::{
+    expr::{Expr, Value},
+    ParseError, Script, ScriptContext, ScriptDirective, ScriptLoader, Span,
+};
+
+/// Attributes read from `character.<name>` when drawing a dialogue
+const CHARACTER_ATTRIBUTES: [&str; 3] = ["text_color", "dialogue_color", "font"];
+
+/// Problem found in a story without running it
+#[derive(Clone, Debug)]
+pub struct Lint {
+    pub file: String,
+    /// Location of the problem, or `None` if it concerns the whole file
+    pub span: Option<Span>,
+    pub why: String,
+}
+
+/// Walks every script reachable from a story's first script
+struct Linter<'l> {
+    loader: &'l dyn ScriptLoader,
+    /// Scripts already linted along with their labels, or `None` if they cannot be parsed
+    scripts: HashMap<String, Option<Vec<String>>>,
+    included: HashSet<String>,
+    /// Scripts to lint along with the jump and label leading to them
+    queue: Vec<(String, Option<Span>, Option<String>)>,
+    shown_sprites: HashSet<String>,
+    hidden_sprites: Vec<(Span, String)>,
+    lints: Vec<Lint>,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.span {
+            Some(span) => write!(
+                f,
+                "Warning on file \"{}\" line {} character {}: {}",
+                span.file, span.line, span.column, self.why
+            ),
+            None => write!(f, "Warning on file \"{}\": {}", self.file, self.why),
+        }
+    }
+}
+
+impl Lint {
+    fn at(span: &Span, why: String) -> Self {
+        Self {
+            file: span.file.clone(),
+            span: Some(span.clone()),
+            why,
+        }
+    }
+}
+
+impl<'l> Linter<'l> {
+    fn lint_script(&mut self, path: String, from: Option<Span>, label: Option<String>) {
+        if let Some(labels) = self.scripts.get(&path) {
+            if let Some(labels) = labels.clone() {
+                self.check_label(&path, &labels, from.as_ref(), label);
+            }
+            return;
+        }
+
+        let script = match Script::load(&path, self.loader) {
+            Ok(script) => script,
+            Err(e) => {
+                self.scripts.insert(path.clone(), None);
+                let lint = |why| match &from {
+                    Some(span) => Lint::at(span, why),
+                    None => Lint {
+                        file: path.clone(),
+                        span: None,
+                        why,
+                    },
+                };
+                match e {
+                    ParseError::Diagnostics(errors) => self.lints.extend(
+                        errors
+                            .into_iter()
+                            .map(|error| Lint::at(&error.span, error.why)),
+                    ),
+                    ParseError::NoFileExists(_) => {
+                        self.lints.push(lint(format!("Missing script {}", path)))
+                    }
+                    e => self.lints.push(lint(e.to_string())),
+                }
+                return;
+            }
+        };
+
+        self.included.extend(script.included.iter().cloned());
+        let labels = script
+            .ctx
+            .iter()
+            .filter_map(|ctx| match ctx {
+                ScriptContext::Directive(ScriptDirective::Label(label)) => Some(label.name.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        self.check_label(&path, &labels, from.as_ref(), label);
+        self.scripts.insert(path, Some(labels));
+
+        for (ctx, span) in script.ctx.iter().zip(&script.spans) {
+            let directive = match ctx {
+                ScriptContext::Directive(directive) => directive,
+                ScriptContext::Dialogue(_) => continue,
+            };
+            match directive {
+                ScriptDirective::Jump(jump) => {
+                    for endpoint in jump.endpoints().filter(|e| !e.is_local()) {
+                        self.queue.push((
+                            endpoint.script_path.clone(),
+                            Some(span.clone()),
+                            endpoint.label.clone(),
+                        ));
+                    }
+                }
+                ScriptDirective::LoadBG(bg) => self.check_file(span, "background", &bg.bg_path),
+                ScriptDirective::Sprite(sprite) => match &sprite.sprite_path {
+                    Some(sprite_path) => {
+                        self.check_file(span, "sprite", sprite_path);
+                        self.shown_sprites.insert(sprite.name.clone());
+                    }
+                    None => self
+                        .hidden_sprites
+                        .push((span.clone(), sprite.name.clone())),
+                },
+                ScriptDirective::Attr(attr) => {
+                    self.check_attribute(span, &attr.path, &attr.key, &attr.value)
+                }
+                ScriptDirective::Set(set) => {
+                    if let Expr::Literal(value) = &set.value {
+                        let value = match value {
+                            Value::Str(s) => s.clone(),
+                            value => value.to_string(),
+                        };
+                        self.check_attribute(span, &set.path, &set.key, &value)
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn check_label(
+        &mut self,
+        path: &str,
+        labels: &[String],
+        from: Option<&Span>,
+        label: Option<String>,
+    ) {
+        if let (Some(span), Some(label)) = (from, label) {
+            if !labels.contains(&label) {
+                self.lints.push(Lint::at(
+                    span,
+                    format!("Unknown label {} in {}", label, path),
+                ));
+            }
+        }
+    }
+
+    fn check_file(&mut self, span: &Span, kind: &str, path: &str) {
+        if !self.loader.exists(path) {
+            self.lints
+                .push(Lint::at(span, format!("Missing {} file {}", kind, path)));
+        }
+    }
+
+    /// Check attributes the renderer parses and ignores if malformed
+    fn check_attribute(&mut self, span: &Span, path: &str, key: &str, value: &str) {
+        let why = match path.split_once('.').map(|(root, _)| root) {
+            Some("character") if !CHARACTER_ATTRIBUTES.contains(&key) => {
+                format!("Unknown character attribute {}", key)
+            }
+            Some("character") if key != "font" && u32::from_str_radix(value, 16).is_err() => {
+                format!("{} must be a hexadecimal RGBA color, got {}", key, value)
+            }
+            Some("sprite") if key == "scale" && value.parse::<f64>().is_err() => {
+                format!("scale must be a number, got {}", value)
+            }
+            Some("sprite") if key == "priority" && value.parse::<i32>().is_err() => {
+                format!("priority must be an integer, got {}", value)
+            }
+            _ => return,
+        };
+        self.lints.push(Lint::at(span, why));
+    }
+}
+
+/// Lint the story starting at the script at start, following every jump
+///
+/// Every script of `story_files` that no jump or include reaches is reported as unreachable.
+pub fn lint(start: &str, loader: &dyn ScriptLoader, story_files: &[String]) -> Vec<Lint> {
+    let mut linter = Linter {
+        loader,
+        scripts: HashMap::new(),
+        included: HashSet::new(),
+        queue: vec![(start.to_string(), None, None)],
+        shown_sprites: HashSet::new(),
+        hidden_sprites: Vec::new(),
+        lints: Vec::new(),
+    };
+    while let Some((path, from, label)) = linter.queue.pop() {
+        linter.lint_script(path, from, label);
+    }
+
+    for (span, name) in linter.hidden_sprites {
+        if !linter.shown_sprites.contains(&name) {
+            linter.lints.push(Lint::at(
+                &span,
+                format!("Sprite {} is hidden but never shown", name),
+            ));
+        }
+    }
+
+    let normalize = |path: &str| Path::new(path.trim_start_matches("./")).to_path_buf();
+    let reached = linter
+        .scripts
+        .keys()
+        .chain(&linter.included)
+        .map(|path| normalize(path))
+        .collect::<HashSet<_>>();
+    for file in story_files {
+        if !reached.contains(&normalize(file)) {
+            linter.lints.push(Lint {
+                file: file.clone(),
+                span: None,
+                why: "Script is never reached".to_string(),
+            });
+        }
+    }
+
+    linter.lints
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::MemoryLoader;
+
+    use super::lint;
+
+    #[test]
+    fn story() {
+        let mut loader = MemoryLoader::new();
+        loader
+            .insert(
+                "a.txt",
+                "@attr(sprite.john.scale, big)\n@attr(character.John.size, x)\n\
+                 @sprite(john, hide)\n@jump(A, b.txt#hall, B, c.txt)\n",
+            )
+            .insert("b.txt", "@label(room)\n")
+            .insert("d.txt", "@label(room)\n");
+
+        let lints = lint(
+            "a.txt",
+            &loader,
+            &[
+                "a.txt".to_string(),
+                "./b.txt".to_string(),
+                "d.txt".to_string(),
+            ],
+        );
+        let mut lints = lints
+            .iter()
+            .map(|lint| (lint.span.as_ref().map(|span| span.line), lint.why.as_str()))
+            .collect::<Vec<_>>();
+        lints.sort();
+        assert_eq!(
+            lints,
+            [
+                (None, "Script is never reached"),
+                (Some(1), "scale must be a number, got big"),
+                (Some(2), "Unknown character attribute size"),
+                (Some(3), "Sprite john is hidden but never shown"),
+                (Some(4), "Missing script c.txt"),
+                (Some(4), "Unknown label hall in b.txt"),
+            ]
+        );
+    }
+}
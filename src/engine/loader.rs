@@ -0,0 +1,200 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use super::ParseError;
+
+/// Files of a game, read by their path relative to the game's root.
+/// Resolves jumps and includes, and is read from for every image the game shows
+pub trait ScriptLoader: Send + Sync {
+    /// Return the content of the script at path
+    fn load(&self, path: &str) -> Result<String, ParseError>;
+
+    /// Return the bytes of the file at path, such as an image
+    fn read(&self, path: &str) -> io::Result<Vec<u8>>;
+
+    fn exists(&self, path: &str) -> bool {
+        self.read(path).is_ok()
+    }
+
+    /// Name of the file at path telling it apart from the files of other games,
+    /// used to share decoded images between engines
+    fn locate(&self, path: &str) -> String {
+        path.to_string()
+    }
+}
+
+impl<L: ScriptLoader + ?Sized> ScriptLoader for Arc<L> {
+    fn load(&self, path: &str) -> Result<String, ParseError> {
+        self.as_ref().load(path)
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.as_ref().read(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.as_ref().exists(path)
+    }
+
+    fn locate(&self, path: &str) -> String {
+        self.as_ref().locate(path)
+    }
+}
+
+/// Loads files from the filesystem, relative to a root directory
+#[derive(Clone, Debug, Default)]
+pub struct FileLoader {
+    /// Empty for the current directory
+    root: PathBuf,
+}
+
+/// Loads scripts and files from memory, keyed by path
+#[derive(Clone, Debug, Default)]
+pub struct MemoryLoader {
+    pub scripts: HashMap<String, String>,
+    pub files: HashMap<String, Vec<u8>>,
+}
+
+/// Resolve path written in the script at base.
+///
+/// Paths starting with `./` or `../` are relative to the directory of base, with `..` going
+/// no further up than the root. Others are relative to the game's root and returned as is,
+/// leaving loaders to refuse those outside the root.
+pub fn resolve(base: &str, path: &str) -> String {
+    if !(path.starts_with("./") || path.starts_with("../")) {
+        return path.to_string();
+    }
+    let mut parts = base.split('/').collect::<Vec<_>>();
+    parts.pop();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+impl FileLoader {
+    /// Load the files of the game whose root directory is root
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of the file at path relative to the root, refusing absolute paths and paths
+    /// whose `..` leave the root
+    pub fn path(&self, path: &str) -> io::Result<PathBuf> {
+        let mut depth = 0;
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(_) => depth += 1,
+                Component::CurDir => {}
+                Component::ParentDir if depth > 0 => depth -= 1,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{} is outside the game's root", path),
+                    ))
+                }
+            }
+        }
+        Ok(self.root.join(path))
+    }
+}
+
+impl ScriptLoader for FileLoader {
+    fn load(&self, path: &str) -> Result<String, ParseError> {
+        self.path(path)
+            .and_then(fs::read_to_string)
+            .map_err(|e| match e {
+                _ if e.kind() == io::ErrorKind::NotFound => {
+                    ParseError::NoFileExists(path.to_owned())
+                }
+                _ => ParseError::IoError(path.to_owned(), e),
+            })
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.path(path).and_then(fs::read)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.path(path).is_ok_and(|path| path.is_file())
+    }
+
+    fn locate(&self, path: &str) -> String {
+        self.root.join(path).to_string_lossy().into_owned()
+    }
+}
+
+impl MemoryLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, path: &str, script: &str) -> &mut Self {
+        self.scripts.insert(path.to_string(), script.to_string());
+        self
+    }
+
+    /// Add a file other than a script, such as an image
+    pub fn insert_file(&mut self, path: &str, bytes: Vec<u8>) -> &mut Self {
+        self.files.insert(path.to_string(), bytes);
+        self
+    }
+}
+
+impl ScriptLoader for MemoryLoader {
+    fn load(&self, path: &str) -> Result<String, ParseError> {
+        self.scripts
+            .get(path)
+            .cloned()
+            .ok_or_else(|| ParseError::NoFileExists(path.to_owned()))
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.files
+            .get(path)
+            .cloned()
+            .or_else(|| {
+                self.scripts
+                    .get(path)
+                    .map(|script| script.clone().into_bytes())
+            })
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, path.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{resolve, FileLoader, ScriptLoader};
+
+    #[test]
+    fn resolve_paths() {
+        assert_eq!(resolve("scripts/a.txt", "scripts/b.txt"), "scripts/b.txt");
+        assert_eq!(resolve("scripts/a.txt", "./b.txt"), "scripts/b.txt");
+        assert_eq!(resolve("scripts/a.txt", "../bgs/x.png"), "bgs/x.png");
+        assert_eq!(resolve("a.txt", "../../x.png"), "x.png");
+    }
+
+    #[test]
+    fn outside_root() {
+        let loader = FileLoader::new("resources");
+        assert!(loader.path("scripts/../bgs/./x.png").is_ok());
+        for path in ["/etc/passwd", "../config.conf", "bgs/../../config.conf"] {
+            assert!(loader.path(path).is_err(), "{} is outside the root", path);
+            assert!(loader.read(path).is_err());
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use std::ops::{Range, RangeInclusive};
+
+use super::frame::Color;
+
+/// Look of a run of dialogue text, set by inline markup
+#[derive(Clone, Debug, PartialEq)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    /// Color of the text, the speaker's text color if `None`
+    pub color: Option<Color>,
+    /// Size relative to the scene's scale
+    pub size: f32,
+}
+
+/// Text drawn in a single style
+#[derive(Clone, Debug, PartialEq)]
+pub struct Run {
+    pub text: String,
+    pub style: Style,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            bold: false,
+            italic: false,
+            color: None,
+            size: 1.0,
+        }
+    }
+}
+
+impl Run {
+    /// Text drawn in the default style
+    pub fn plain(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            style: Style::default(),
+        }
+    }
+}
+
+/// Sizes a `{size=…}` tag may set, relative to the scene's
+const SIZES: RangeInclusive<f32> = 0.25..=4.0;
+
+/// Styles opened by the tags seen so far, innermost last
+#[derive(Default)]
+struct Tags {
+    bold: usize,
+    italic: usize,
+    colors: Vec<Color>,
+    sizes: Vec<f32>,
+}
+
+impl Tags {
+    /// Apply tag, returning whether it is markup
+    fn apply(&mut self, tag: &str) -> bool {
+        match tag {
+            "b" => self.bold += 1,
+            "/b" => self.bold = self.bold.saturating_sub(1),
+            "i" => self.italic += 1,
+            "/i" => self.italic = self.italic.saturating_sub(1),
+            "/color" => {
+                self.colors.pop();
+            }
+            "/size" => {
+                self.sizes.pop();
+            }
+            _ => match tag.split_once('=') {
+                Some(("color", color)) => match parse_color(color) {
+                    Some(color) => self.colors.push(color),
+                    None => return false,
+                },
+                Some(("size", size)) => match size.parse::<f32>() {
+                    Ok(size) if SIZES.contains(&size) => self.sizes.push(size),
+                    _ => return false,
+                },
+                _ => return false,
+            },
+        }
+        true
+    }
+
+    fn style(&self) -> Style {
+        Style {
+            bold: self.bold > 0,
+            italic: self.italic > 0,
+            color: self.colors.last().copied(),
+            size: self.sizes.last().copied().unwrap_or(1.0),
+        }
+    }
+}
+
+/// Text of runs without their styles
+pub fn plain_text(runs: &[Run]) -> String {
+    runs.iter().map(|run| run.text.as_str()).collect()
+}
+
+/// Part of runs within range of their text, each part keeping the style of its run
+pub fn slice(runs: &[Run], range: Range<usize>) -> Vec<Run> {
+    let mut start = 0;
+    runs.iter()
+        .filter_map(|run| {
+            let (from, to) = (start, start + run.text.len());
+            start = to;
+            let (first, last) = (range.start.max(from), range.end.min(to));
+            (first < last).then(|| Run {
+                text: run.text[first - from..last - from].to_string(),
+                style: run.style.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Parse an `RRGGBB` or `RRGGBBAA` hexadecimal color
+fn parse_color(color: &str) -> Option<Color> {
+    let value = u32::from_str_radix(color, 16).ok()?;
+    match color.len() {
+        6 => Some((value << 8 | 0xff).to_be_bytes()),
+        8 => Some(value.to_be_bytes()),
+        _ => None,
+    }
+}
+
+/// Split dialogue into runs by its `{b}`, `{i}`, `{color=RRGGBB}` and `{size=1.2}` tags, each
+/// closed by `{/b}`, `{/i}`, `{/color}` and `{/size}`. `{br}` breaks the line.
+///
+/// Anything else in braces is left in the text, along with `{{` escapes, so the runs can be
+/// interpolated afterwards without variables being read as markup.
+pub fn parse(text: &str) -> Vec<Run> {
+    let mut runs = Vec::new();
+    let mut tags = Tags::default();
+    let mut current = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        current.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(escaped) = rest.strip_prefix("{{") {
+            current.push_str("{{");
+            rest = escaped;
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("{br}") {
+            current.push('\n');
+            rest = after;
+            continue;
+        }
+
+        let style = tags.style();
+        match rest.find('}') {
+            Some(end) if tags.apply(&rest[1..end]) => {
+                if !current.is_empty() {
+                    runs.push(Run {
+                        text: std::mem::take(&mut current),
+                        style,
+                    });
+                }
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                current.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    current.push_str(rest);
+    if !current.is_empty() {
+        runs.push(Run {
+            text: current,
+            style: tags.style(),
+        });
+    }
+
+    runs
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, plain_text, slice, Run, Style};
+
+    #[test]
+    fn markup() {
+        let runs = parse(
+            "a {b}bold {color=ff0000}red{/b} {size=1.5}{i}big{/i}{/size}{/color} {x} {{b}{br}",
+        );
+        let style = |bold, italic, color: Option<[u8; 4]>, size| Style {
+            bold,
+            italic,
+            color,
+            size,
+        };
+        let red = Some([255, 0, 0, 255]);
+        assert_eq!(
+            runs,
+            [
+                Run::plain("a "),
+                Run {
+                    text: "bold ".to_string(),
+                    style: style(true, false, None, 1.0),
+                },
+                Run {
+                    text: "red".to_string(),
+                    style: style(true, false, red, 1.0),
+                },
+                Run {
+                    text: " ".to_string(),
+                    style: style(false, false, red, 1.0),
+                },
+                Run {
+                    text: "big".to_string(),
+                    style: style(false, true, red, 1.5),
+                },
+                Run::plain(" {x} {{b}\n"),
+            ]
+        );
+
+        let text = plain_text(&runs);
+        let start = text.find("old").unwrap();
+        assert_eq!(
+            slice(&runs, start..start + "old red".len()),
+            [
+                Run {
+                    text: "old ".to_string(),
+                    style: style(true, false, None, 1.0),
+                },
+                Run {
+                    text: "red".to_string(),
+                    style: style(true, false, red, 1.0),
+                },
+            ]
+        );
+
+        // Malformed tags are kept as text
+        assert_eq!(
+            parse("{color=red}{size=-1}{b"),
+            [Run::plain("{color=red}{size=-1}{b")]
+        );
+        // So are sizes out of bounds
+        assert_eq!(
+            parse("{size=inf}{size=1e30}{size=NaN}{size=0.1}"),
+            [Run::plain("{size=inf}{size=1e30}{size=NaN}{size=0.1}")]
+        );
+    }
+}
@@ -1,12 +1,32 @@
+pub mod args;
+pub mod backlog;
 pub mod config;
+pub mod custom;
 pub mod directives;
 #[allow(clippy::module_inception)]
 pub mod engine;
 pub mod errors;
+pub mod expr;
+pub mod frame;
+pub mod graph;
+pub mod lint;
+pub mod loader;
+pub mod markup;
+pub mod package;
+pub mod prefetch;
+pub mod read;
+pub mod save;
 pub mod script;
 
+pub use backlog::{BacklogEntry, BacklogKind};
 pub use config::Config;
+pub use custom::{CustomArgs, CustomHandler, CustomRegistry};
 pub use directives::*;
 pub use engine::Engine;
 pub use errors::*;
+pub use frame::{Frame, FrameContent, FrameSprite};
+pub use loader::{FileLoader, MemoryLoader, ScriptLoader};
+pub use package::Package;
+pub use read::ReadLog;
+pub use save::SaveData;
 pub use script::{Script, ScriptContext, ScriptDirective};
@@ -0,0 +1,229 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use zip::{result::ZipError, write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use super::{PackageError, ParseError, ScriptLoader};
+
+/// Version of the package format written by this engine
+pub const PACKAGE_VERSION: u32 = 1;
+
+/// Path of the manifest within a package
+pub const MANIFEST_PATH: &str = "manifest.json";
+
+/// Files under the game's root written while playing rather than part of the game
+const EXCLUDED: [&str; 5] = [
+    "resources/render",
+    "resources/saves",
+    "resources/save.json",
+    "resources/read.json",
+    "resources/tmp.png",
+];
+
+/// Lists the files of a package
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// Paths of every file in the package relative to the game's root, sorted
+    pub files: Vec<String>,
+}
+
+/// Game packed into a single zip archive, its files read from the archive by their path
+/// relative to the game's root
+pub struct Package<R = File> {
+    /// Where the archive was opened from, telling its files apart from those of other games
+    name: String,
+    manifest: Manifest,
+    archive: Mutex<ZipArchive<R>>,
+}
+
+impl Manifest {
+    /// Parse a manifest, refusing manifests written by a newer format
+    pub fn from_json(json: &str) -> Result<Self, PackageError> {
+        let manifest =
+            serde_json::from_str::<serde_json::Value>(json).map_err(PackageError::Malformed)?;
+        if let Some(version) = manifest.get("version").and_then(serde_json::Value::as_u64) {
+            if version > PACKAGE_VERSION as u64 {
+                return Err(PackageError::UnsupportedVersion(version, PACKAGE_VERSION));
+            }
+        }
+        serde_json::from_value(manifest).map_err(PackageError::Malformed)
+    }
+}
+
+/// Every file of the game at root, relative to root with `/` separators
+fn game_files(root: &Path, dir: &Path, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let relative = path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if EXCLUDED.contains(&relative.as_str()) || relative.ends_with(".tmp") {
+            continue;
+        }
+        if path.is_dir() {
+            game_files(root, &path, files)?;
+        } else {
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+/// Pack every file under `resources` in the game at root into the archive at path.
+/// Renders and saves are left out
+pub fn pack(root: &Path, path: &Path) -> Result<Manifest, PackageError> {
+    let io_error = |e| PackageError::IoError(path.to_string_lossy().into_owned(), e);
+    let mut files = Vec::new();
+    game_files(root, &root.join("resources"), &mut files)
+        .map_err(|e| PackageError::IoError(root.to_string_lossy().into_owned(), e))?;
+    files.sort();
+    let manifest = Manifest {
+        version: PACKAGE_VERSION,
+        files,
+    };
+
+    let mut writer = ZipWriter::new(File::create(path).map_err(io_error)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    writer.start_file(MANIFEST_PATH, options)?;
+    writer
+        .write_all(
+            serde_json::to_string_pretty(&manifest)
+                .expect("Unable to serialize manifest")
+                .as_bytes(),
+        )
+        .map_err(io_error)?;
+    for file in &manifest.files {
+        let content =
+            fs::read(root.join(file)).map_err(|e| PackageError::IoError(file.to_string(), e))?;
+        writer.start_file(file, options)?;
+        writer.write_all(&content).map_err(io_error)?;
+    }
+    writer.finish()?;
+    Ok(manifest)
+}
+
+impl Package {
+    /// Open the package at path, checking it holds every file its manifest lists
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, PackageError> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|e| PackageError::IoError(path.to_string_lossy().into_owned(), e))?;
+        Self::from_reader(&path.to_string_lossy(), file)
+    }
+}
+
+impl<R: Read + Seek> Package<R> {
+    /// Open the package read from reader, name tells its files apart from other games'
+    pub fn from_reader(name: &str, reader: R) -> Result<Self, PackageError> {
+        let mut archive = ZipArchive::new(reader)?;
+        let mut json = String::new();
+        archive
+            .by_name(MANIFEST_PATH)?
+            .read_to_string(&mut json)
+            .map_err(|e| PackageError::IoError(MANIFEST_PATH.to_string(), e))?;
+        let manifest = Manifest::from_json(&json)?;
+        if let Some(missing) = manifest
+            .files
+            .iter()
+            .find(|file| archive.by_name(file).is_err())
+        {
+            return Err(PackageError::MissingFile(missing.clone()));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            manifest,
+            archive: Mutex::new(archive),
+        })
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+}
+
+impl<R: Read + Seek + Send> ScriptLoader for Package<R> {
+    fn load(&self, path: &str) -> Result<String, ParseError> {
+        let bytes = self.read(path).map_err(|e| match e {
+            _ if e.kind() == io::ErrorKind::NotFound => ParseError::NoFileExists(path.to_owned()),
+            _ => ParseError::IoError(path.to_owned(), e),
+        })?;
+        String::from_utf8(bytes).map_err(|e| {
+            ParseError::IoError(
+                path.to_owned(),
+                io::Error::new(io::ErrorKind::InvalidData, e),
+            )
+        })
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        let mut archive = self.archive.lock().unwrap();
+        let mut file = archive.by_name(path).map_err(|e| match e {
+            ZipError::Io(e) => e,
+            ZipError::FileNotFound => io::Error::new(io::ErrorKind::NotFound, path.to_string()),
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        })?;
+        let mut bytes = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.manifest.files.iter().any(|file| file == path)
+    }
+
+    fn locate(&self, path: &str) -> String {
+        format!("{}!{}", self.name, path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, io::Cursor};
+
+    use crate::engine::{PackageError, ScriptLoader};
+
+    use super::{pack, Package};
+
+    #[test]
+    fn pack_and_open() {
+        let root = std::env::temp_dir().join("image_rpg_package");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("resources/scripts")).unwrap();
+        fs::create_dir_all(root.join("resources/saves")).unwrap();
+        fs::write(root.join("resources/scripts/a.txt"), "[]\nhi\n").unwrap();
+        fs::write(root.join("resources/bg.png"), [1, 2, 3]).unwrap();
+        fs::write(root.join("resources/saves/1.json"), "{}").unwrap();
+
+        let path = root.join("game.zip");
+        let manifest = pack(&root, &path).unwrap();
+        assert_eq!(
+            manifest.files,
+            ["resources/bg.png", "resources/scripts/a.txt"]
+        );
+
+        let package = Package::open(&path).unwrap();
+        assert_eq!(package.load("resources/scripts/a.txt").unwrap(), "[]\nhi\n");
+        assert_eq!(package.read("resources/bg.png").unwrap(), [1, 2, 3]);
+        assert!(package.exists("resources/bg.png"));
+        assert!(!package.exists("resources/saves/1.json"));
+        assert!(package.read("resources/missing.png").is_err());
+
+        let truncated = fs::read(&path).unwrap();
+        assert!(Package::from_reader("game.zip", Cursor::new(&truncated[..10])).is_err());
+        assert!(matches!(
+            super::Manifest::from_json(r#"{"version": 99, "files": []}"#),
+            Err(PackageError::UnsupportedVersion(99, 1))
+        ));
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread,
+};
+
+use image::{io::Reader, DynamicImage};
+use log::{debug, warn};
+
+use super::ScriptLoader;
+use crate::img::{decode_image, error::LoadImageError};
+
+/// Paths to decode for a prefetch, read through its loader
+type Job = (u64, Vec<String>, Arc<dyn ScriptLoader>);
+
+/// Memory prefetched images may take by default, in bytes
+pub const DEFAULT_PREFETCH_BUDGET: usize = 64 * 1024 * 1024;
+
+/// Decodes images the story is about to show on a worker thread
+pub struct Prefetcher {
+    shared: Arc<Shared>,
+    /// Started on the first prefetch
+    worker: Option<Sender<Job>>,
+    budget: usize,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    /// Notified whenever a pending image is decoded or dropped
+    decoded: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    /// Incremented by every prefetch, cancelling the images of previous ones
+    generation: u64,
+    images: HashMap<String, Slot>,
+    /// Bytes taken by decoded images
+    used: usize,
+}
+
+enum Slot {
+    /// Waiting for the worker
+    Queued,
+    /// Being decoded by the worker
+    Pending,
+    Ready(Arc<DynamicImage>, usize),
+    /// Left for the engine to load again and report the error
+    Failed,
+}
+
+impl Prefetcher {
+    /// Prefetch at most budget bytes of decoded images at once, or nothing if budget is 0
+    pub fn new(budget: usize) -> Self {
+        Self {
+            shared: Arc::new(Shared::default()),
+            worker: None,
+            budget,
+        }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.shared.state.lock().unwrap()
+    }
+
+    /// Start decoding paths read through loader in order, cancelling previous prefetches and
+    /// dropping the images they decoded that are not in paths
+    pub fn prefetch(&mut self, paths: Vec<String>, loader: Arc<dyn ScriptLoader>) {
+        if self.budget == 0 {
+            return;
+        }
+        let generation = {
+            let mut state = self.state();
+            state.generation += 1;
+            state.images.retain(|path, _| paths.contains(path));
+            for path in &paths {
+                state.images.entry(path.clone()).or_insert(Slot::Queued);
+            }
+            state.used = state
+                .images
+                .values()
+                .map(|slot| match slot {
+                    Slot::Ready(_, bytes) => *bytes,
+                    Slot::Queued | Slot::Pending | Slot::Failed => 0,
+                })
+                .sum();
+            state.generation
+        };
+        self.shared.decoded.notify_all();
+        if paths.is_empty() {
+            return;
+        }
+
+        let (shared, budget) = (self.shared.clone(), self.budget);
+        let worker = self.worker.get_or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel::<Job>();
+            thread::spawn(move || {
+                for (generation, paths, loader) in receiver {
+                    decode(&shared, budget, generation, paths, loader.as_ref());
+                }
+            });
+            sender
+        });
+        // The worker only stops once the engine, and so the sender, is dropped
+        let _ = worker.send((generation, paths, loader));
+    }
+
+    /// Take the decoded image at path, waiting for it if it is queued or being decoded.
+    /// Returns `None` if path was not prefetched, did not fit the budget or cannot be decoded
+    pub fn take(&self, path: &str) -> Option<Arc<DynamicImage>> {
+        let mut state = self.state();
+        loop {
+            match state.images.get(path) {
+                Some(Slot::Queued | Slot::Pending) => {
+                    state = self.shared.decoded.wait(state).unwrap()
+                }
+                Some(Slot::Ready(..)) => {
+                    return match state.images.remove(path) {
+                        Some(Slot::Ready(image, bytes)) => {
+                            state.used -= bytes;
+                            Some(image)
+                        }
+                        _ => unreachable!(),
+                    };
+                }
+                Some(Slot::Failed) | None => {
+                    state.images.remove(path);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// Decode paths until they are done, the budget is spent or a newer prefetch cancels them
+fn decode(
+    shared: &Shared,
+    budget: usize,
+    generation: u64,
+    paths: Vec<String>,
+    loader: &dyn ScriptLoader,
+) {
+    let mut paths = paths.into_iter();
+    while let Some(path) = paths.next() {
+        let encoded = loader.read(&path);
+        // Estimate the decoded size from the header to stay within budget
+        let bytes = encoded
+            .as_ref()
+            .ok()
+            .and_then(|encoded| {
+                Reader::new(Cursor::new(encoded))
+                    .with_guessed_format()
+                    .ok()?
+                    .into_dimensions()
+                    .ok()
+            })
+            .map(|(width, height)| width as usize * height as usize * 4)
+            .unwrap_or(0);
+        {
+            let mut state = shared.state.lock().unwrap();
+            if state.generation != generation {
+                debug!("Prefetch cancelled before {}", path);
+                return;
+            }
+            // Skip images taken or decoded since they were queued
+            if !matches!(state.images.get(&path), Some(Slot::Queued)) {
+                continue;
+            }
+            if state.used + bytes > budget {
+                debug!("Prefetch budget spent before {}", path);
+                // Leave the rest for the engine to load
+                for path in std::iter::once(path).chain(paths) {
+                    if let Some(Slot::Queued) = state.images.get(&path) {
+                        state.images.remove(&path);
+                    }
+                }
+                drop(state);
+                shared.decoded.notify_all();
+                return;
+            }
+            state.images.insert(path.clone(), Slot::Pending);
+        }
+
+        let slot = match encoded
+            .map_err(LoadImageError::IoError)
+            .and_then(|encoded| decode_image(&encoded))
+        {
+            Ok(image) => {
+                debug!("Prefetched {}", path);
+                let bytes = image.as_bytes().len();
+                Slot::Ready(Arc::new(image), bytes)
+            }
+            Err(e) => {
+                warn!("Cannot prefetch {}: {}", path, e);
+                Slot::Failed
+            }
+        };
+        let mut guard = shared.state.lock().unwrap();
+        let state = &mut *guard;
+        // Cancelled prefetches drop their pending images
+        if let Some(pending) = state.images.get_mut(&path) {
+            if let Slot::Ready(_, bytes) = &slot {
+                state.used += bytes;
+            }
+            *pending = slot;
+        }
+        drop(guard);
+        shared.decoded.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::GenericImageView;
+
+    use std::sync::Arc;
+
+    use crate::img::fixtures::images;
+
+    use super::Prefetcher;
+
+    #[test]
+    fn prefetch() {
+        let (small, large) = ("small.png".to_string(), "large.png".to_string());
+        let files = Arc::new(images(&[(&small, 8), (&large, 64)]));
+        let mut prefetcher = Prefetcher::new(1024);
+        prefetcher.prefetch(
+            vec![small.clone(), large.clone(), "missing.png".to_string()],
+            files.clone(),
+        );
+        assert_eq!(prefetcher.take(&small).unwrap().dimensions(), (8, 8));
+        // Taken images are not kept, and large ones do not fit the budget
+        assert!(prefetcher.take(&small).is_none());
+        assert!(prefetcher.take(&large).is_none());
+
+        prefetcher.prefetch(vec![small.clone()], files.clone());
+        prefetcher.prefetch(Vec::new(), files.clone());
+        assert!(prefetcher.take(&small).is_none());
+
+        let mut disabled = Prefetcher::new(0);
+        disabled.prefetch(vec![small.clone()], files);
+        assert!(disabled.take(&small).is_none());
+    }
+}
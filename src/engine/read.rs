@@ -0,0 +1,82 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{save::write_file, script::ScriptDialogue, SaveError};
+
+/// Dialogues a player has already read, by script and a hash of their speaker and text so
+/// edits moving them within their script keep them read. Identical dialogues of a script are
+/// read together. Kept apart from saves so it outlives every playthrough
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReadLog {
+    scripts: BTreeMap<String, BTreeSet<u64>>,
+}
+
+/// Hash of the speaker and text of dialogue, with FNV-1a as it must not change between builds
+fn key(dialogue: &ScriptDialogue) -> u64 {
+    let text = dialogue.dialogues.join("\n");
+    let bytes = dialogue
+        .character_name
+        .bytes()
+        .chain([0])
+        .chain(text.bytes());
+    bytes.fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+impl ReadLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_read(&self, script: &str, dialogue: &ScriptDialogue) -> bool {
+        self.scripts
+            .get(script)
+            .is_some_and(|read| read.contains(&key(dialogue)))
+    }
+
+    /// Mark dialogue of script as read, returning whether it was unread
+    pub fn mark(&mut self, script: &str, dialogue: &ScriptDialogue) -> bool {
+        self.scripts
+            .entry(script.to_string())
+            .or_default()
+            .insert(key(dialogue))
+    }
+
+    /// Number of dialogues read
+    pub fn len(&self) -> usize {
+        self.scripts.values().map(BTreeSet::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Unable to serialize read log")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SaveError> {
+        serde_json::from_str(json).map_err(SaveError::Malformed)
+    }
+
+    /// Load the log at path, or an empty log if nothing was read yet
+    pub fn load(path: &str) -> Result<Self, SaveError> {
+        if !Path::new(path).exists() {
+            return Ok(Self::new());
+        }
+        Self::from_json(
+            &fs::read_to_string(path).map_err(|e| SaveError::IoError(path.to_string(), e))?,
+        )
+    }
+
+    /// Write the log to path, creating its directory if needed
+    pub fn write_to_file(&self, path: &str) -> Result<(), SaveError> {
+        write_file(path, &self.to_json())
+    }
+}
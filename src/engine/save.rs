@@ -0,0 +1,68 @@
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::{engine::Attributes, BacklogEntry, SaveError, SpriteDirective};
+
+/// Version of the save format written by this engine
+pub const SAVE_VERSION: u32 = 1;
+
+/// Write contents to path, creating its directory if needed
+pub(crate) fn write_file(path: &str, contents: &str) -> Result<(), SaveError> {
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir).map_err(|e| SaveError::IoError(path.to_string(), e))?;
+    }
+    // Write to a temporary file first so a crash never leaves a truncated save
+    let tmp = format!("{}.tmp", path);
+    fs::write(&tmp, contents)
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|e| SaveError::IoError(path.to_string(), e))
+}
+
+/// Everything needed to resume an `Engine` where it was saved
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveData {
+    pub version: u32,
+    pub script_path: String,
+    pub iscript: usize,
+    /// Page shown of the dialogue at `iscript`
+    #[serde(default)]
+    pub page: usize,
+    pub sprites: Vec<SpriteDirective>,
+    pub bg_path: Option<String>,
+    /// Attributes along with every variable set by `@set`
+    pub attributes: Attributes,
+    #[serde(default)]
+    pub backlog: Vec<BacklogEntry>,
+}
+
+impl SaveData {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Unable to serialize save data")
+    }
+
+    /// Parse a save, refusing saves written by a newer format
+    pub fn from_json(json: &str) -> Result<Self, SaveError> {
+        let save = serde_json::from_str::<serde_json::Value>(json).map_err(SaveError::Malformed)?;
+        let version = save
+            .get("version")
+            .and_then(serde_json::Value::as_u64)
+            .ok_or(SaveError::MissingVersion)?;
+        if version > SAVE_VERSION as u64 {
+            return Err(SaveError::UnsupportedVersion(version, SAVE_VERSION));
+        }
+
+        serde_json::from_value(save).map_err(SaveError::Malformed)
+    }
+
+    /// Write the save to path, creating its directory if needed
+    pub fn write_to_file(&self, path: &str) -> Result<(), SaveError> {
+        write_file(path, &self.to_json())
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, SaveError> {
+        Self::from_json(
+            &fs::read_to_string(path).map_err(|e| SaveError::IoError(path.to_string(), e))?,
+        )
+    }
+}
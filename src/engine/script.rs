@@ -1,14 +1,22 @@
 use super::{
-    directives::{Directive, JumpDirective, LoadBGDirective, SpriteDirective},
-    AttributeDirective, CustomDirective, ParseError,
+    args::split_args,
+    directives::{Directive, JumpDirective, LabelDirective, LoadBGDirective, SpriteDirective},
+    loader::resolve,
+    AttributeDirective, ConditionDirective, CustomDirective, FileLoader, IncludeDirective,
+    MacroDirective, ParseError, ScriptLoader, SetDirective, Span, SyntaxError,
 };
-use std::{fmt::Debug, fs, io};
+use std::{collections::HashMap, fmt::Debug, io::Read, ops::Range};
 #[derive(Clone, Debug)]
 pub struct Script {
     /// Name of the script file
     pub name: String,
     /// Parsed script's content
     pub ctx: Vec<ScriptContext>,
+    /// Location of the token each context was parsed from, the name of a dialogue's
+    /// character or a directive's name and arguments
+    pub spans: Vec<Span>,
+    /// Every file included while parsing the script
+    pub included: Vec<String>,
     // References to other script files
     //refs: Option<Box<HashMap<String, Script>>>,
 }
@@ -32,131 +40,716 @@ pub enum ScriptDirective {
     LoadBG(LoadBGDirective),
     Attr(AttributeDirective),
     Custom(CustomDirective),
+    Label(LabelDirective),
+    Set(SetDirective),
+    If(ConditionDirective),
+    Elif(ConditionDirective),
+    Else,
+    EndIf,
 }
 
 macro_rules! to_syntax_error {
-    ($err:expr,$path:expr,$line:expr,$character:expr) => {
-        $err.map_err(|e| ParseError::SyntaxError($path, $line, $character, format!("{}", e)))
+    ($err:expr,$source:expr,$token:expr) => {
+        $err.map_err(|e| $source.error($token.span.clone(), format!("{}", e)))
     };
 }
 
-impl Script {
-    pub fn from_file(path: &str) -> Result<Self, ParseError> {
-        let mut ctx: Vec<ScriptContext> = Vec::new();
-
-        for (i, line) in fs::read_to_string(path)
-            .map_err(|e| match e {
-                _ if e.kind() == io::ErrorKind::NotFound => {
-                    ParseError::NoFileExists(path.to_owned())
-                }
-                _ => panic!("Cannot open file {} because {}", path, e),
-            })?
-            .split('\n')
-            .enumerate()
-            .map(|(i, line)| (i + 1, line))
-        {
-            if line.starts_with('[') {
-                let iend = line.rfind(']').ok_or_else(|| {
-                    ParseError::SyntaxError(
-                        path.to_string(),
-                        i,
-                        line.len(),
-                        "Expected closing ]".into(),
-                    )
-                })?;
+/// Directives which cannot be redefined by macros
+const BUILTIN_DIRECTIVES: [&str; 14] = [
+    "jump", "sprite", "loadbg", "attr", "custom", "label", "set", "if", "elif", "else", "endif",
+    "include", "macro", "endmacro",
+];
+const MAX_EXPANSION_DEPTH: usize = 32;
 
-                ctx.push(ScriptContext::Dialogue(ScriptDialogue {
-                    character_name: line.get(1..iend).map(str::to_string).unwrap(),
-                    dialogues: Vec::new(),
-                }))
-            } else if line.starts_with('#') {
-                continue;
-            } else if line.starts_with('@') {
-                let directive_iend = line.find('(').ok_or_else(|| {
-                    ParseError::SyntaxError(path.to_string(), i, line.len(), "Expected (".into())
-                })?;
-                let directive = line.get(1..directive_iend).unwrap();
-                let ctx_iend = line.rfind(')').ok_or_else(|| {
-                    ParseError::SyntaxError(
-                        path.to_string(),
-                        i,
-                        line.len(),
-                        "Expected closing parentheses".into(),
+/// A line of a script file, without its line ending
+#[derive(Clone, Debug)]
+struct SourceLine {
+    file: String,
+    line: usize,
+    /// Byte offset of the line within the file
+    offset: usize,
+    text: String,
+    /// Line of a macro's body the line was expanded from, if it was
+    expansion: Option<Box<Expansion>>,
+}
+
+/// Macro arguments substituted into a line of a macro's body
+#[derive(Clone, Debug)]
+struct Expansion {
+    body: SourceLine,
+    /// Byte range of each argument in the expanded line and of its parameter in the body
+    args: Vec<(Range<usize>, Range<usize>)>,
+}
+
+/// Slice of a line along with its byte range within the line
+#[derive(Clone, Debug)]
+struct Token<'a> {
+    text: &'a str,
+    span: Range<usize>,
+}
+
+#[derive(Clone, Debug)]
+enum Line<'a> {
+    Blank,
+    Comment,
+    Character(Token<'a>),
+    Directive { name: Token<'a>, args: Token<'a> },
+    Dialogue(Token<'a>),
+}
+
+#[derive(Clone, Debug)]
+struct Macro {
+    definition: MacroDirective,
+    /// Where the macro is defined
+    defined_at: Span,
+    body: Vec<SourceLine>,
+}
+
+/// Parser state shared by a script and the scripts it includes
+struct ScriptParser<'l> {
+    loader: &'l dyn ScriptLoader,
+    ctx: Vec<ScriptContext>,
+    spans: Vec<Span>,
+    /// Jumps to labels within the script, checked once every label is known
+    local_jumps: Vec<(Span, String)>,
+    /// Every unclosed @if and whether it has reached its @else
+    blocks: Vec<(Span, bool)>,
+    macros: HashMap<String, Macro>,
+    /// Macro whose body is currently being read
+    defining: Option<Macro>,
+    /// Files currently being parsed, outermost first
+    includes: Vec<String>,
+    included: Vec<String>,
+    expansion_depth: usize,
+    diagnostics: Vec<SyntaxError>,
+}
+
+impl<'a> Token<'a> {
+    fn new(line: &'a str, span: Range<usize>) -> Self {
+        Self {
+            text: &line[span.clone()],
+            span,
+        }
+    }
+}
+
+/// Split a line into its tokens, or return the span and reason it cannot be
+fn lex_line(text: &str) -> Result<Line<'_>, (Range<usize>, String)> {
+    if text.trim().is_empty() {
+        Ok(Line::Blank)
+    } else if text.starts_with('#') {
+        Ok(Line::Comment)
+    } else if text.starts_with('[') {
+        let end = text
+            .rfind(']')
+            .ok_or_else(|| (text.len()..text.len(), "Expected closing ]".to_string()))?;
+        Ok(Line::Character(Token::new(text, 1..end)))
+    } else if text.starts_with('@') {
+        // Directives without arguments such as @else may omit the parentheses
+        let (name, args) = match text.find('(') {
+            Some(open) => {
+                let close = text
+                    .rfind(')')
+                    .filter(|close| *close > open)
+                    .ok_or_else(|| {
+                        (
+                            text.len()..text.len(),
+                            "Expected closing parentheses".to_string(),
+                        )
+                    })?;
+                let after = &text[close + 1..];
+                if !after.trim().is_empty() {
+                    return Err((
+                        text.len() - after.trim_start().len()..text.len(),
+                        "Unexpected characters after closing parentheses".to_string(),
+                    ));
+                }
+                (1..open, open + 1..close)
+            }
+            None => {
+                let end = text.trim_end().len();
+                (1..end, end..end)
+            }
+        };
+        let name = Token::new(text, name);
+        if name.text.trim().is_empty() || name.text.contains(char::is_whitespace) {
+            return Err((name.span, "Expected directive name".to_string()));
+        }
+        Ok(Line::Directive {
+            name,
+            args: Token::new(text, args),
+        })
+    } else {
+        Ok(Line::Dialogue(Token::new(text, 0..text.len())))
+    }
+}
+
+impl SourceLine {
+    /// Line as written in its file, before macro arguments were substituted into it
+    fn written(&self) -> &SourceLine {
+        match &self.expansion {
+            Some(expansion) => expansion.body.written(),
+            None => self,
+        }
+    }
+
+    /// Byte offset in the line as written of the byte at pos in the line, or of the end of
+    /// the byte before it if end. Bytes of a substituted argument are those of its parameter
+    fn written_offset(&self, pos: usize, end: bool) -> usize {
+        let expansion = match &self.expansion {
+            Some(expansion) => expansion,
+            None => return pos,
+        };
+        let mut body = pos;
+        for (arg, param) in &expansion.args {
+            if pos <= arg.start {
+                break;
+            }
+            if pos < arg.end {
+                body = if end { param.end } else { param.start };
+                break;
+            }
+            body = param.end + (pos - arg.end);
+        }
+        expansion.body.written_offset(body, end)
+    }
+
+    fn span(&self, span: Range<usize>) -> Span {
+        let start = self.written_offset(span.start, false);
+        let end = self.written_offset(span.end, true).max(start);
+        Span {
+            file: self.file.clone(),
+            line: self.line,
+            column: self.written().text[..start].chars().count() + 1,
+            bytes: self.offset + start..self.offset + end,
+        }
+    }
+
+    fn error(&self, span: Range<usize>, why: String) -> SyntaxError {
+        SyntaxError {
+            span: self.span(span),
+            why,
+        }
+    }
+}
+
+impl Macro {
+    /// Substitute the arguments in context into the macro's body
+    fn expand(&self, ctx: &str) -> Result<Vec<SourceLine>, ParseError> {
+        let args = split_args("macro", ctx)?;
+        let params = &self.definition.params;
+        if args.len() != params.len() {
+            return Err(ParseError::DirectiveError(
+                "macro",
+                format!(
+                    "{} expects {} arguments, got {}",
+                    self.definition.name,
+                    params.len(),
+                    args.len()
+                ),
+            ));
+        }
+
+        // Longer parameters are substituted first so $name never clobbers $name_2
+        let mut substitutions = params.iter().zip(args).collect::<Vec<_>>();
+        substitutions.sort_by_key(|(param, _)| std::cmp::Reverse(param.len()));
+
+        Ok(self
+            .body
+            .iter()
+            .map(|source| {
+                let mut text = String::with_capacity(source.text.len());
+                let mut args = Vec::new();
+                let mut rest = 0;
+                while let Some(found) = source.text[rest..].find('$') {
+                    let dollar = rest + found;
+                    let after = &source.text[dollar + 1..];
+                    match substitutions
+                        .iter()
+                        .find(|(param, _)| after.starts_with(param.as_str()))
+                    {
+                        Some((param, arg)) => {
+                            text.push_str(&source.text[rest..dollar]);
+                            let start = text.len();
+                            text.push_str(arg);
+                            rest = dollar + 1 + param.len();
+                            args.push((start..text.len(), dollar..rest));
+                        }
+                        None => {
+                            text.push_str(&source.text[rest..dollar + 1]);
+                            rest = dollar + 1;
+                        }
+                    }
+                }
+                text.push_str(&source.text[rest..]);
+
+                SourceLine {
+                    text,
+                    expansion: Some(Box::new(Expansion {
+                        body: source.clone(),
+                        args,
+                    })),
+                    ..source.clone()
+                }
+            })
+            .collect())
+    }
+}
+
+impl<'l> ScriptParser<'l> {
+    fn new(loader: &'l dyn ScriptLoader) -> Self {
+        Self {
+            loader,
+            ctx: Vec::new(),
+            spans: Vec::new(),
+            local_jumps: Vec::new(),
+            blocks: Vec::new(),
+            macros: HashMap::new(),
+            defining: None,
+            includes: Vec::new(),
+            included: Vec::new(),
+            expansion_depth: 0,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn parse_file(&mut self, path: &str) -> Result<(), ParseError> {
+        let contents = self.loader.load(path)?;
+        self.parse_source(path, &contents);
+        Ok(())
+    }
+
+    fn parse_source(&mut self, name: &str, contents: &str) {
+        let (contents, mut offset) = match contents.strip_prefix('\u{feff}') {
+            Some(contents) => (contents, '\u{feff}'.len_utf8()),
+            None => (contents, 0),
+        };
+
+        self.includes.push(name.to_string());
+        for (i, line) in contents.split('\n').enumerate() {
+            self.parse_line(&SourceLine {
+                file: name.to_string(),
+                line: i + 1,
+                offset,
+                text: line.strip_suffix('\r').unwrap_or(line).to_string(),
+                expansion: None,
+            });
+            offset += line.len() + 1;
+        }
+        self.includes.pop();
+    }
+
+    /// Check what can only be checked once the whole script is read
+    fn finish(mut self, name: &str) -> Result<Script, ParseError> {
+        if let Some(definition) = self.defining.take() {
+            self.diagnostics.push(SyntaxError {
+                span: definition.defined_at,
+                why: format!(
+                    "Unclosed macro {}, expected endmacro",
+                    definition.definition.name
+                ),
+            });
+        }
+
+        for (span, _) in self.blocks.drain(..) {
+            self.diagnostics.push(SyntaxError {
+                span,
+                why: "Unclosed if, expected endif".to_string(),
+            });
+        }
+
+        let script = Script {
+            name: name.to_string(),
+            ctx: self.ctx,
+            spans: self.spans,
+            included: self.included,
+        };
+
+        for (span, label) in self.local_jumps {
+            if script.label(&label).is_none() {
+                self.diagnostics.push(SyntaxError {
+                    span,
+                    why: format!("Unknown label {}", label),
+                });
+            }
+        }
+
+        if !self.diagnostics.is_empty() {
+            return Err(ParseError::Diagnostics(self.diagnostics));
+        }
+
+        Ok(script)
+    }
+
+    /// Parse a line, recording its error if it has one
+    fn parse_line(&mut self, source: &SourceLine) {
+        if let Err(e) = self.try_parse_line(source) {
+            self.diagnostics.push(e);
+        }
+    }
+
+    fn push(&mut self, ctx: ScriptContext, span: Span) {
+        self.ctx.push(ctx);
+        self.spans.push(span);
+    }
+
+    fn try_parse_line(&mut self, source: &SourceLine) -> Result<(), SyntaxError> {
+        if let Some(definition) = self.defining.as_mut() {
+            if source.text.trim_end().starts_with("@endmacro") {
+                let definition = self.defining.take().unwrap();
+                self.macros
+                    .insert(definition.definition.name.clone(), definition);
+            } else {
+                definition.body.push(source.clone());
+            }
+            return Ok(());
+        }
+
+        let line_span = source.span(0..source.text.len());
+        let line = match lex_line(&source.text) {
+            Ok(line) => line,
+            Err((span, why)) => {
+                // Keep an unclosed character so the dialogue below it isn't reported too
+                if source.text.starts_with('[') {
+                    self.push(
+                        ScriptContext::Dialogue(ScriptDialogue {
+                            character_name: source.text[1..].to_string(),
+                            dialogues: Vec::new(),
+                        }),
+                        line_span,
+                    );
+                }
+                return Err(source.error(span, why));
+            }
+        };
+
+        let (name, args) =
+            match line {
+                Line::Blank | Line::Comment => return Ok(()),
+                Line::Character(name) => {
+                    self.push(
+                        ScriptContext::Dialogue(ScriptDialogue {
+                            character_name: name.text.to_string(),
+                            dialogues: Vec::new(),
+                        }),
+                        source.span(name.span),
+                    );
+                    return Ok(());
+                }
+                Line::Dialogue(text) => {
+                    return match self.ctx.last_mut() {
+                        Some(ScriptContext::Dialogue(dialogue)) => {
+                            dialogue.dialogues.push(text.text.to_string());
+                            Ok(())
+                        }
+                        _ => Err(source
+                            .error(text.span, "Unmatched dialogue with character".to_string())),
+                    };
+                }
+                Line::Directive { name, args } => (name, args),
+            };
+        let context = args.text;
+        let directive_span = source.span(name.span.start..args.span.end);
+
+        let directive = match name.text {
+            "jump" => {
+                let mut jump =
+                    to_syntax_error!(JumpDirective::from_context(context), source, args)?;
+                for endpoint in jump.endpoints_mut().filter(|e| !e.is_local()) {
+                    endpoint.script_path = resolve(&source.file, &endpoint.script_path);
+                }
+                for endpoint in jump.endpoints().filter(|e| e.is_local()) {
+                    if let Some(label) = &endpoint.label {
+                        self.local_jumps
+                            .push((source.span(args.span.clone()), label.clone()));
+                    }
+                }
+                ScriptDirective::Jump(jump)
+            }
+            "sprite" => {
+                let mut sprite =
+                    to_syntax_error!(SpriteDirective::from_context(context), source, args)?;
+                sprite.sprite_path = sprite.sprite_path.map(|path| resolve(&source.file, &path));
+                ScriptDirective::Sprite(sprite)
+            }
+            "loadbg" => {
+                let mut bg =
+                    to_syntax_error!(LoadBGDirective::from_context(context), source, args)?;
+                bg.bg_path = resolve(&source.file, &bg.bg_path);
+                ScriptDirective::LoadBG(bg)
+            }
+            "attr" => ScriptDirective::Attr(to_syntax_error!(
+                AttributeDirective::from_context(context),
+                source,
+                args
+            )?),
+            "custom" => ScriptDirective::Custom(to_syntax_error!(
+                CustomDirective::from_context(context),
+                source,
+                args
+            )?),
+            "label" => {
+                let label = to_syntax_error!(LabelDirective::from_context(context), source, args)?;
+                if self.ctx.iter().any(|c| match c {
+                    ScriptContext::Directive(ScriptDirective::Label(l)) => l.name == label.name,
+                    _ => false,
+                }) {
+                    return Err(source.error(
+                        args.span,
+                        format!("Label {} is already defined", label.name),
+                    ));
+                }
+                ScriptDirective::Label(label)
+            }
+            "set" => ScriptDirective::Set(to_syntax_error!(
+                SetDirective::from_context(context),
+                source,
+                args
+            )?),
+            "if" => {
+                self.blocks.push((source.span(name.span.clone()), false));
+                ScriptDirective::If(to_syntax_error!(
+                    ConditionDirective::from_context(context),
+                    source,
+                    args
+                )?)
+            }
+            "elif" | "else" | "endif" => {
+                let (_, seen_else) = self.blocks.last_mut().ok_or_else(|| {
+                    source.error(
+                        name.span.clone(),
+                        format!("{} without matching if", name.text),
                     )
                 })?;
-                let context = line.get(directive_iend + 1..ctx_iend).unwrap();
-
-                ctx.push(ScriptContext::Directive(match directive {
-                    "jump" => ScriptDirective::Jump(to_syntax_error!(
-                        JumpDirective::from_context(context),
-                        path.to_string(),
-                        i,
-                        line.len()
-                    )?),
-                    "sprite" => ScriptDirective::Sprite(to_syntax_error!(
-                        SpriteDirective::from_context(context),
-                        path.to_string(),
-                        i,
-                        line.len()
-                    )?),
-                    "loadbg" => ScriptDirective::LoadBG(to_syntax_error!(
-                        LoadBGDirective::from_context(context),
-                        path.to_string(),
-                        i,
-                        line.len()
-                    )?),
-                    "attr" => ScriptDirective::Attr(to_syntax_error!(
-                        AttributeDirective::from_context(context),
-                        path.to_string(),
-                        i,
-                        line.len()
-                    )?),
-                    "custom" => ScriptDirective::Custom(to_syntax_error!(
-                        CustomDirective::from_context(context),
-                        path.to_string(),
-                        i,
-                        line.len()
+                if *seen_else && name.text != "endif" {
+                    return Err(source.error(name.span, format!("{} after else", name.text)));
+                }
+                match name.text {
+                    "elif" => ScriptDirective::Elif(to_syntax_error!(
+                        ConditionDirective::from_context(context),
+                        source,
+                        args
                     )?),
-
-                    directive => {
-                        return Err(ParseError::SyntaxError(
-                            path.to_string(),
-                            i,
-                            line.len(),
-                            format!("Unknown directive {}", directive),
-                        ))
+                    "else" => {
+                        *seen_else = true;
+                        ScriptDirective::Else
                     }
-                }));
-            } else if !line.trim().is_empty() {
-                // the rest here must be dialogues
-                match ctx.last_mut().ok_or_else(|| {
-                    ParseError::SyntaxError(
-                        path.to_string(),
-                        i,
-                        0,
-                        "Unmatched dialogue with character".to_string(),
-                    )
-                })? {
-                    ScriptContext::Dialogue(dialogue) => dialogue.dialogues.push({
-                        let mut line = line.to_string();
-                        line.retain(|c| c != '\r');
-                        line
-                    }),
                     _ => {
-                        return Err(ParseError::SyntaxError(
-                            path.to_string(),
-                            i,
-                            0,
-                            "Unmatched dialogue with character".to_string(),
-                        ))
+                        self.blocks.pop();
+                        ScriptDirective::EndIf
                     }
                 }
             }
-        }
+            "include" => {
+                let mut include =
+                    to_syntax_error!(IncludeDirective::from_context(context), source, args)?;
+                include.path = resolve(&source.file, &include.path);
+                if self.includes.contains(&include.path) {
+                    return Err(
+                        source.error(args.span, format!("{} includes itself", include.path))
+                    );
+                }
+                if !self.included.contains(&include.path) {
+                    self.included.push(include.path.clone());
+                }
+                return to_syntax_error!(self.parse_file(&include.path), source, args);
+            }
+            "macro" => {
+                let definition =
+                    to_syntax_error!(MacroDirective::from_context(context), source, args)?;
+                if BUILTIN_DIRECTIVES.contains(&definition.name.as_str()) {
+                    return Err(source.error(
+                        args.span,
+                        format!("Macro {} shadows a directive", definition.name),
+                    ));
+                }
+                self.defining = Some(Macro {
+                    definition,
+                    defined_at: source.span(name.span),
+                    body: Vec::new(),
+                });
+                return Ok(());
+            }
+            "endmacro" => {
+                return Err(source.error(name.span, "endmacro without matching macro".to_string()))
+            }
+            directive => {
+                let expansion = self
+                    .macros
+                    .get(directive)
+                    .ok_or_else(|| {
+                        source.error(
+                            name.span.clone(),
+                            format!("Unknown directive {}", directive),
+                        )
+                    })
+                    .and_then(|m| to_syntax_error!(m.expand(context), source, args))?;
+                if self.expansion_depth >= MAX_EXPANSION_DEPTH {
+                    return Err(
+                        source.error(name.span, format!("Macro {} expands too deeply", directive))
+                    );
+                }
+                self.expansion_depth += 1;
+                for line in &expansion {
+                    self.parse_line(line);
+                }
+                self.expansion_depth -= 1;
+                return Ok(());
+            }
+        };
+        self.push(ScriptContext::Directive(directive), directive_span);
+
+        Ok(())
+    }
+}
+
+impl Script {
+    /// Parse the script file at path, reporting every syntax error in it at once
+    pub fn from_file(path: &str) -> Result<Self, ParseError> {
+        Self::load(path, &FileLoader::default())
+    }
+
+    /// Parse the script at path read through loader, which also resolves includes
+    pub fn load(path: &str, loader: &dyn ScriptLoader) -> Result<Self, ParseError> {
+        let mut parser = ScriptParser::new(loader);
+        parser.parse_file(path)?;
+        parser.finish(path)
+    }
+
+    /// Parse a script from its source, including files from the filesystem
+    pub fn parse(name: &str, source: &str) -> Result<Self, ParseError> {
+        Self::parse_with(name, source, &FileLoader::default())
+    }
+
+    /// Parse a script from its source, including files through loader
+    pub fn parse_with(
+        name: &str,
+        source: &str,
+        loader: &dyn ScriptLoader,
+    ) -> Result<Self, ParseError> {
+        let mut parser = ScriptParser::new(loader);
+        parser.parse_source(name, source);
+        parser.finish(name)
+    }
 
-        Ok(Self {
-            name: path.to_string(),
-            ctx,
+    /// Parse a script from a reader
+    pub fn from_reader<R: Read>(name: &str, mut reader: R) -> Result<Self, ParseError> {
+        let mut source = String::new();
+        reader
+            .read_to_string(&mut source)
+            .map_err(|e| ParseError::IoError(name.to_string(), e))?;
+        Self::parse(name, &source)
+    }
+    /// Return the position of the label with the given name
+    pub fn label(&self, name: &str) -> Option<usize> {
+        self.ctx.iter().position(|ctx| {
+            matches!(ctx, ScriptContext::Directive(ScriptDirective::Label(label)) if label.name == name)
         })
     }
+
+    /// Return the position of the branch following the conditional at `from`,
+    /// that is its next @elif, @else or @endif
+    pub fn next_branch(&self, from: usize) -> Option<usize> {
+        let mut depth = 0;
+        for (i, ctx) in self.ctx.iter().enumerate().skip(from + 1) {
+            match ctx {
+                ScriptContext::Directive(ScriptDirective::If(_)) => depth += 1,
+                ScriptContext::Directive(ScriptDirective::EndIf) if depth > 0 => depth -= 1,
+                ScriptContext::Directive(
+                    ScriptDirective::Elif(_) | ScriptDirective::Else | ScriptDirective::EndIf,
+                ) if depth == 0 => return Some(i),
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::engine::{MemoryLoader, ParseError, ScriptContext, ScriptDirective};
+
+    use super::Script;
+
+    #[test]
+    fn parse() {
+        let script = Script::parse(
+            "script.txt",
+            "\u{feff}@label(start)\r\n[Alice]\r\nHello\r\n@if(met)\r\n@else\r\n@endif\r\n",
+        )
+        .unwrap();
+        assert_eq!(script.ctx.len(), 5);
+        assert_eq!(script.label("start"), Some(0));
+        assert_eq!(script.next_branch(2), Some(3));
+        assert_eq!(script.spans[1].line, 2);
+        match &script.ctx[1] {
+            ScriptContext::Dialogue(dialogue) => assert_eq!(dialogue.dialogues, ["Hello"]),
+            ctx => panic!("expected dialogue, got {:?}", ctx),
+        }
+    }
+
+    #[test]
+    fn include_and_macros() {
+        let mut loader = MemoryLoader::new();
+        loader.insert(
+            "common.txt",
+            "@macro(enter, who, x)\n@sprite($who, $who.png, $x, 0, show)\n@endmacro\n\
+             @macro(ask, question)\n@jump(\"$question\", , No, #end)\n@label(end)\n@endmacro\n",
+        );
+        let script = Script::parse_with(
+            "script.txt",
+            "@include(common.txt)\n@enter(john, 320)\n@ask(\"Wait, what?\")\n",
+            &loader,
+        )
+        .unwrap();
+        match &script.ctx[..] {
+            [ScriptContext::Directive(ScriptDirective::Sprite(sprite)), ScriptContext::Directive(ScriptDirective::Jump(jump)), _] =>
+            {
+                assert_eq!(sprite.sprite_path.as_deref(), Some("john.png"));
+                assert_eq!(sprite.x, Some(320));
+                assert_eq!(jump.choices.as_ref().unwrap()[0].text, "Wait, what?");
+            }
+            ctx => panic!("expected a sprite, jump and label, got {:?}", ctx),
+        }
+
+        // Expanded contexts are located in the macro's body
+        let span = &script.spans[0];
+        assert_eq!(
+            (span.file.as_str(), span.line, span.column),
+            ("common.txt", 2, 2)
+        );
+        assert_eq!(span.bytes, 23..57);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let errors = match Script::parse(
+            "script.txt",
+            "[Alice\nHello\n@bogus(1)\n@if(1 +)\n@jump(#nowhere)\n",
+        ) {
+            Err(ParseError::Diagnostics(errors)) => errors,
+            result => panic!("expected diagnostics, got {:?}", result),
+        };
+        let positions = errors
+            .iter()
+            .map(|e| (e.span.line, e.span.column))
+            .collect::<Vec<_>>();
+        assert_eq!(positions, [(1, 7), (3, 2), (4, 5), (4, 2), (5, 7)]);
+        assert_eq!(errors[1].span.bytes, 14..19);
+
+        // Bad sprite coordinates are reported at the arguments
+        match Script::parse("script.txt", "@sprite(john, john.png, left, 0, show)\n") {
+            Err(ParseError::Diagnostics(errors)) => assert_eq!(errors[0].span.column, 9),
+            result => panic!("expected diagnostics, got {:?}", result),
+        }
+
+        // Errors in substituted arguments point at their parameter
+        let errors =
+            match Script::parse("script.txt", "@macro(m, d)\n@$d(1)\n@endmacro\n@m(bogus)\n") {
+                Err(ParseError::Diagnostics(errors)) => errors,
+                result => panic!("expected diagnostics, got {:?}", result),
+            };
+        assert_eq!((errors[0].span.line, errors[0].span.column), (2, 2));
+        assert_eq!(errors[0].span.bytes, 14..16);
+    }
 }
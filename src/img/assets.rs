@@ -0,0 +1,230 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+};
+
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+use log::trace;
+
+use super::{error::LoadImageError, read_image};
+use crate::engine::ScriptLoader;
+
+/// Memory the shared assets may take by default, in bytes
+pub const DEFAULT_ASSET_BUDGET: usize = 256 * 1024 * 1024;
+
+/// Decoded images shared between engines, keyed by where their loader locates them and scale.
+///
+/// Clones share the same images. Once over budget, the least recently used images no one
+/// else holds are dropped; images still held stay alive until their last holder drops them.
+#[derive(Clone)]
+pub struct Assets {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Key {
+    path: String,
+    /// Bits of the scale, `None` for the image as decoded
+    scale: Option<u64>,
+}
+
+struct Entry {
+    image: Arc<DynamicImage>,
+    bytes: usize,
+    last_used: u64,
+}
+
+struct Inner {
+    entries: HashMap<Key, Entry>,
+    /// Incremented by every use to order entries by recency
+    clock: u64,
+    used: usize,
+    budget: usize,
+}
+
+impl Key {
+    fn new(loader: &dyn ScriptLoader, path: &str, scale: Option<f64>) -> Self {
+        Self {
+            path: loader.locate(path),
+            scale: scale.map(f64::to_bits),
+        }
+    }
+}
+
+impl Inner {
+    fn get(&mut self, key: &Key) -> Option<Arc<DynamicImage>> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.last_used = self.clock;
+        Some(entry.image.clone())
+    }
+
+    /// Cache image under key, keeping the one already cached if another engine raced us
+    fn insert(&mut self, key: Key, image: Arc<DynamicImage>) -> Arc<DynamicImage> {
+        if let Some(cached) = self.get(&key) {
+            return cached;
+        }
+        let bytes = image.as_bytes().len();
+        self.used += bytes;
+        self.entries.insert(
+            key,
+            Entry {
+                image: image.clone(),
+                bytes,
+                last_used: self.clock,
+            },
+        );
+        self.evict();
+        image
+    }
+
+    /// Drop the least recently used images held by no one else until within budget
+    fn evict(&mut self) {
+        if self.used <= self.budget {
+            return;
+        }
+        let mut unused = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| Arc::strong_count(&entry.image) == 1)
+            .map(|(key, entry)| (entry.last_used, key.clone()))
+            .collect::<Vec<_>>();
+        unused.sort_unstable_by_key(|(last_used, _)| *last_used);
+
+        for (_, key) in unused {
+            if self.used <= self.budget {
+                break;
+            }
+            if let Some(entry) = self.entries.remove(&key) {
+                trace!(
+                    "Evicting {} at scale {:?}",
+                    key.path,
+                    key.scale.map(f64::from_bits)
+                );
+                self.used -= entry.bytes;
+            }
+        }
+    }
+}
+
+impl Assets {
+    /// Keep at most budget bytes of images no one holds
+    pub fn new(budget: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                entries: HashMap::new(),
+                clock: 0,
+                used: 0,
+                budget,
+            })),
+        }
+    }
+
+    /// Assets shared by every engine of the process unless given others
+    pub fn shared() -> Self {
+        static SHARED: OnceLock<Assets> = OnceLock::new();
+        SHARED
+            .get_or_init(|| Self::new(DEFAULT_ASSET_BUDGET))
+            .clone()
+    }
+
+    fn inner(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap()
+    }
+
+    pub fn contains(&self, loader: &dyn ScriptLoader, path: &str) -> bool {
+        self.inner()
+            .entries
+            .contains_key(&Key::new(loader, path, None))
+    }
+
+    /// Bytes taken by cached images
+    pub fn used(&self) -> usize {
+        self.inner().used
+    }
+
+    /// Cache an image decoded elsewhere, returning the cached image
+    pub fn insert(
+        &self,
+        loader: &dyn ScriptLoader,
+        path: &str,
+        image: Arc<DynamicImage>,
+    ) -> Arc<DynamicImage> {
+        self.inner().insert(Key::new(loader, path, None), image)
+    }
+
+    /// The image at path read through loader, decoding it if it is not cached
+    pub fn get(
+        &self,
+        loader: &dyn ScriptLoader,
+        path: &str,
+    ) -> Result<Arc<DynamicImage>, LoadImageError> {
+        let key = Key::new(loader, path, None);
+        if let Some(image) = self.inner().get(&key) {
+            return Ok(image);
+        }
+        // Decode without holding the lock so other engines are not kept waiting
+        let image = Arc::new(read_image(loader, path)?);
+        Ok(self.inner().insert(key, image))
+    }
+
+    /// The image at path resized by scale, resizing and caching it if it is not cached
+    pub fn get_scaled(
+        &self,
+        loader: &dyn ScriptLoader,
+        path: &str,
+        scale: f64,
+    ) -> Result<Arc<DynamicImage>, LoadImageError> {
+        let key = Key::new(loader, path, Some(scale));
+        if let Some(image) = self.inner().get(&key) {
+            return Ok(image);
+        }
+        let image = self.get(loader, path)?;
+        let (width, height) = image.dimensions();
+        let scaled = Arc::new(image.resize_exact(
+            (width as f64 * scale) as u32,
+            (height as f64 * scale) as u32,
+            FilterType::Gaussian,
+        ));
+        Ok(self.inner().insert(key, scaled))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use image::GenericImageView;
+
+    use crate::img::fixtures::images;
+
+    use super::Assets;
+
+    #[test]
+    fn lru() {
+        // Room for two 8x8 images and a 4x4 one
+        let assets = Assets::new(2 * 8 * 8 * 4 + 4 * 4 * 4);
+        let (a, b, c) = ("a.png", "b.png", "c.png");
+        let files = images(&[(a, 8), (b, 8), (c, 8)]);
+
+        let held = assets.get(&files, a).unwrap();
+        assert!(Arc::ptr_eq(&held, &assets.get(&files, a).unwrap()));
+        drop(assets.get(&files, b).unwrap());
+        assert_eq!(
+            assets.get_scaled(&files, b, 0.5).unwrap().dimensions(),
+            (4, 4)
+        );
+        assert_eq!(assets.used(), 2 * 8 * 8 * 4 + 4 * 4 * 4);
+
+        // b is evicted rather than the held a, even though a was used before it
+        drop(assets.get(&files, c).unwrap());
+        assert!(
+            assets.contains(&files, a) && !assets.contains(&files, b) && assets.contains(&files, c)
+        );
+
+        drop(held);
+        assets.get(&files, b).unwrap();
+        assert!(!assets.contains(&files, a));
+        assert!(assets.get(&files, "missing.png").is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use std::io::Cursor;
+
 use image::{io::Reader, DynamicImage, ImageBuffer, Pixel, Rgba};
 use imageproc::{
     drawing::{draw_filled_rect_mut, draw_line_segment_mut, Canvas},
@@ -5,54 +7,36 @@ use imageproc::{
 };
 use rusttype::{Font, Point, PositionedGlyph, Scale};
 
-use super::error::LoadImageError;
+use super::{error::LoadImageError, font::layout, layout::Line};
+use crate::engine::{markup::Style, ScriptLoader};
 
-pub fn draw_words<'a, 'i>(
-    text: &str,
-    color: &Rgba<u8>,
+/// Draw a laid out line with its start at x on the baseline at y. Runs without a color of
+/// their own are drawn in color
+pub fn draw_line<'i>(
     image: &'i mut ImageBuffer<Rgba<u8>, Vec<u8>>,
-    font: &Font<'a>,
-    scale: Scale,
-    point: Point<f32>,
-    xmax: u32,
+    line: &Line,
+    color: &Rgba<u8>,
+    x: f32,
+    y: f32,
 ) -> &'i mut ImageBuffer<Rgba<u8>, Vec<u8>> {
-    let v_metrics = font.v_metrics(scale);
-    let glyphs_height = (v_metrics.ascent - v_metrics.descent).ceil() as u32;
-
-    let glyphs_vec: Vec<_> = text
-        .split(' ')
-        .map(|word| font.layout(word, scale, point).collect::<Vec<_>>())
-        .collect();
-    let whitespace_width = glyphs_width(&font.layout("_", scale, point).collect::<Vec<_>>());
-
-    let (mut xcur, mut ycur) = (0, 0);
-    for glyphs in glyphs_vec {
-        if !glyphs.is_empty() {
-            let width = glyphs_width(&glyphs);
-
-            if xcur + width + whitespace_width > xmax {
-                xcur = 0;
-                ycur += glyphs_height;
-            }
-
-            draw_layout(image, &glyphs, color, xcur as f32, ycur as f32);
-
-            xcur += width + whitespace_width;
-        }
+    for piece in &line.pieces {
+        let color = piece.style.color.map_or(*color, Rgba);
+        draw_layout(image, &piece.glyphs, &color, &piece.style, x, y);
     }
 
     image
 }
 
+/// Draw text on a single line, every character in the first of fonts having a glyph for it
 pub fn draw_text<'a, 'i>(
     text: &str,
     color: &Rgba<u8>,
     image: &'i mut ImageBuffer<Rgba<u8>, Vec<u8>>,
-    font: &Font<'a>,
+    fonts: &[&Font<'a>],
     scale: Scale,
     point: Point<f32>,
 ) -> &'i mut ImageBuffer<Rgba<u8>, Vec<u8>> {
-    let glyphs: Vec<_> = font.layout(text, scale, point).collect();
+    let glyphs = layout(fonts, text, scale, point);
 
     for glyph in &glyphs {
         if let Some(bounding_box) = glyph.pixel_bounding_box() {
@@ -72,65 +56,68 @@ pub fn draw_text<'a, 'i>(
     image
 }
 
+/// Slant of italic text, in pixels right per pixel above the baseline
+const ITALIC_SLANT: f32 = 0.2;
+
+/// Draw glyphs laid out on the baseline at 0 offset by xoffset and yoffset. Bold glyphs are
+/// drawn twice a pixel apart and italic glyphs slanted, as fonts have no bold or italic faces
 fn draw_layout<'a>(
     image: &'a mut ImageBuffer<Rgba<u8>, Vec<u8>>,
     layout: &[PositionedGlyph],
     color: &Rgba<u8>,
+    style: &Style,
     xoffset: f32,
     yoffset: f32,
 ) -> &'a mut ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let slant = if style.italic { ITALIC_SLANT } else { 0.0 };
+    let strokes: &[f32] = if style.bold { &[0.0, 1.0] } else { &[0.0] };
     for glyph in layout {
         if let Some(bounding_box) = glyph.pixel_bounding_box() {
-            glyph.draw(|x, y, v| {
-                let image_x = ((x + bounding_box.min.x as u32) as f32 + xoffset)
-                    .floor()
-                    .max(0.0) as u32;
-                let image_y = ((y + bounding_box.min.y as u32) as f32 + yoffset)
-                    .floor()
-                    .max(0.0) as u32;
-
-                let pixel = image.get_pixel(image_x, image_y);
-
-                let pix = pixel.map2(color, |p, q| {
-                    ((p as f32 * (1.0 - v) + q as f32 * v) as u8).clamp(0, 255)
-                });
-                image.put_pixel(image_x, image_y, pix)
-            })
+            for stroke in strokes {
+                glyph.draw(|x, y, v| {
+                    let y = (y as i32 + bounding_box.min.y) as f32;
+                    let x = (x as i32 + bounding_box.min.x) as f32 + stroke - y * slant;
+                    let (image_x, image_y) = ((x + xoffset).floor(), (y + yoffset).floor());
+                    if image_x < 0.0
+                        || image_y < 0.0
+                        || image_x as u32 >= image.width()
+                        || image_y as u32 >= image.height()
+                    {
+                        return;
+                    }
+                    let (image_x, image_y) = (image_x as u32, image_y as u32);
+
+                    let pixel = image.get_pixel(image_x, image_y);
+
+                    let pix = pixel.map2(color, |p, q| {
+                        ((p as f32 * (1.0 - v) + q as f32 * v) as u8).clamp(0, 255)
+                    });
+                    image.put_pixel(image_x, image_y, pix)
+                })
+            }
         }
     }
     image
 }
 
-#[inline]
-fn layout_width(layout: &[PositionedGlyph]) -> (i32, i32) {
-    let min_x = layout
-        .first()
-        .map(|g| g.pixel_bounding_box().unwrap().min.x)
-        .unwrap();
-    let max_x = layout
-        .last()
-        .map(|g| g.pixel_bounding_box().unwrap().max.x)
-        .unwrap();
-    (min_x, max_x)
-}
-
-#[inline]
-pub fn glyphs_width(glyphs: &[PositionedGlyph]) -> u32 {
-    let (min_x, max_x) = layout_width(glyphs);
-    (max_x - min_x) as u32
+/// Load the image at path relative to the current directory
+#[deprecated(note = "read images relative to the game's root with `read_image`")]
+pub fn load_image(path: &str) -> Result<DynamicImage, LoadImageError> {
+    Reader::open(path)
+        .map_err(LoadImageError::IoError)?
+        .decode()
+        .map_err(LoadImageError::ImageError)
 }
 
-pub fn as_glyphs<'a>(
-    text: &str,
-    font: &'a Font<'a>,
-    scale: Scale,
-    point: Point<f32>,
-) -> Vec<PositionedGlyph<'a>> {
-    font.layout(text, scale, point).collect::<Vec<_>>()
+/// Load the image at path from the files of a game
+pub fn read_image(loader: &dyn ScriptLoader, path: &str) -> Result<DynamicImage, LoadImageError> {
+    decode_image(&loader.read(path).map_err(LoadImageError::IoError)?)
 }
 
-pub fn load_image(path: &str) -> Result<DynamicImage, LoadImageError> {
-    Reader::open(path)
+/// Decode an image from its encoded bytes, guessing its format
+pub fn decode_image(bytes: &[u8]) -> Result<DynamicImage, LoadImageError> {
+    Reader::new(Cursor::new(bytes))
+        .with_guessed_format()
         .map_err(LoadImageError::IoError)?
         .decode()
         .map_err(LoadImageError::ImageError)
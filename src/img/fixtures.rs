@@ -0,0 +1,23 @@
+//! Images shared by the tests
+
+use image::{DynamicImage, ImageOutputFormat};
+
+use crate::engine::MemoryLoader;
+
+/// Transparent PNG of width by height
+pub fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut png = Vec::new();
+    DynamicImage::new_rgba8(width, height)
+        .write_to(&mut png, ImageOutputFormat::Png)
+        .unwrap();
+    png
+}
+
+/// Loader holding a square PNG of each size at its path
+pub fn images(images: &[(&str, u32)]) -> MemoryLoader {
+    let mut loader = MemoryLoader::new();
+    for &(path, size) in images {
+        loader.insert_file(path, png(size, size));
+    }
+    loader
+}
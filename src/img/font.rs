@@ -0,0 +1,77 @@
+use rusttype::{point, Font, GlyphId, Point, PositionedGlyph, Scale};
+
+/// Index of the first of fonts having a glyph for c, or of the first font if none has
+pub fn font_for(fonts: &[&Font<'_>], c: char) -> usize {
+    fonts
+        .iter()
+        .position(|font| font.glyph(c).id() != GlyphId(0))
+        .unwrap_or(0)
+}
+
+/// Lay out text on a single line starting at the baseline at start, drawing every character
+/// with the first of fonts having a glyph for it
+pub fn layout<'a>(
+    fonts: &[&Font<'a>],
+    text: &str,
+    scale: Scale,
+    start: Point<f32>,
+) -> Vec<PositionedGlyph<'a>> {
+    let mut caret = 0.0;
+    let mut last: Option<(usize, GlyphId)> = None;
+    text.chars()
+        .map(|c| {
+            let i = font_for(fonts, c);
+            let glyph = fonts[i].glyph(c).scaled(scale);
+            // Kerning only applies between glyphs of the same font
+            if let Some((last_font, last_glyph)) = last {
+                if last_font == i {
+                    caret += fonts[i].pair_kerning(scale, last_glyph, glyph.id());
+                }
+            }
+            last = Some((i, glyph.id()));
+            let advance = glyph.h_metrics().advance_width;
+            let glyph = glyph.positioned(point(start.x + caret, start.y));
+            caret += advance;
+            glyph
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use rusttype::{point, Font, Scale};
+
+    use super::{font_for, layout};
+
+    /// Cyrillic letter Qa, which Courier New has but Calibri does not
+    const MISSING_FROM_CALIBRI: char = '\u{51a}';
+
+    #[test]
+    fn fallback() {
+        let calibri =
+            Font::try_from_bytes(include_bytes!("../../resources/fonts/calibri-regular.ttf"))
+                .unwrap();
+        let courier =
+            Font::try_from_bytes(include_bytes!("../../resources/fonts/cour.ttf")).unwrap();
+        let fonts = [&calibri, &courier];
+
+        assert_eq!(font_for(&fonts, 'a'), 0);
+        assert_eq!(font_for(&fonts, MISSING_FROM_CALIBRI), 1);
+        // Characters no font has are left to the first
+        assert_eq!(font_for(&fonts, '\u{e000}'), 0);
+
+        let scale = Scale::uniform(24.0);
+        let glyphs = layout(&fonts, "a b", scale, point(10.0, 20.0));
+        let expected = calibri
+            .layout("a b", scale, point(10.0, 20.0))
+            .map(|glyph| glyph.position())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            glyphs
+                .iter()
+                .map(|glyph| glyph.position())
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+}
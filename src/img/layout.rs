@@ -0,0 +1,273 @@
+use std::ops::Range;
+
+use rusttype::{point, Font, GlyphId, PositionedGlyph, Scale};
+use unicode_linebreak::{linebreaks, BreakOpportunity};
+
+use super::font::font_for;
+use crate::engine::markup::{plain_text, Run, Style};
+
+/// Glyphs of part of a run laid out on a line
+pub struct Piece<'a> {
+    /// Positioned relative to the start of the line's baseline
+    pub glyphs: Vec<PositionedGlyph<'a>>,
+    pub style: Style,
+}
+
+/// Line of laid out text and the box it takes
+pub struct Line<'a> {
+    pub pieces: Vec<Piece<'a>>,
+    /// Bytes of the text of the runs laid out on the line
+    pub range: Range<usize>,
+    /// Width up to the end of the last glyph that is not whitespace
+    pub width: f32,
+    /// Distance from the top of the line to its baseline
+    pub ascent: f32,
+    /// Height of the line, that of its largest run
+    pub height: f32,
+}
+
+/// Character of the text laid out on a single unbroken line
+struct Char<'a> {
+    /// Byte index of the character in the text
+    index: usize,
+    run: usize,
+    c: char,
+    glyph: PositionedGlyph<'a>,
+    advance: f32,
+}
+
+impl Char<'_> {
+    fn left(&self) -> f32 {
+        self.glyph.position().x
+    }
+
+    fn right(&self) -> f32 {
+        self.left() + self.advance
+    }
+}
+
+/// Lay out every character of runs on a single line, each in the first of fonts having a
+/// glyph for it. Kerning applies between neighbouring glyphs of the same font and size, spaces
+/// and run boundaries included
+fn place<'a>(fonts: &[&Font<'a>], runs: &[Run], scale: Scale) -> Vec<Char<'a>> {
+    let mut chars = Vec::new();
+    let mut caret = 0.0;
+    let mut last: Option<(usize, f32, GlyphId)> = None;
+    let mut start = 0;
+    for (i, run) in runs.iter().enumerate() {
+        let size = run.style.size;
+        let scale = Scale::uniform(scale.x * size);
+        for (offset, c) in run.text.char_indices() {
+            // Line breaks and other control characters take no space
+            if c.is_control() {
+                last = None;
+                chars.push(Char {
+                    index: start + offset,
+                    run: i,
+                    c,
+                    glyph: fonts[0]
+                        .glyph(' ')
+                        .scaled(scale)
+                        .positioned(point(caret, 0.)),
+                    advance: 0.,
+                });
+                continue;
+            }
+
+            let font = font_for(fonts, c);
+            let glyph = fonts[font].glyph(c).scaled(scale);
+            if let Some((last_font, last_size, last_glyph)) = last {
+                if last_font == font && last_size == size {
+                    caret += fonts[font].pair_kerning(scale, last_glyph, glyph.id());
+                }
+            }
+            last = Some((font, size, glyph.id()));
+            let advance = glyph.h_metrics().advance_width;
+            chars.push(Char {
+                index: start + offset,
+                run: i,
+                c,
+                glyph: glyph.positioned(point(caret, 0.)),
+                advance,
+            });
+            caret += advance;
+        }
+        start += run.text.len();
+    }
+    chars
+}
+
+/// End of the last of chars that is not whitespace
+fn visible_right(chars: &[Char]) -> Option<f32> {
+    chars
+        .iter()
+        .rev()
+        .find(|c| !c.c.is_whitespace())
+        .map(Char::right)
+}
+
+/// Width of chars laid out from the first of them, up to the last that is not whitespace
+fn line_width(chars: &[Char]) -> f32 {
+    match (chars.first(), visible_right(chars)) {
+        (Some(first), Some(right)) => right - first.left(),
+        _ => 0.,
+    }
+}
+
+/// Gather chars into a line starting at the first of them
+fn line<'a>(fonts: &[&Font<'a>], runs: &[Run], scale: Scale, chars: &[Char<'a>]) -> Line<'a> {
+    let left = chars.first().map_or(0., Char::left);
+    let mut pieces: Vec<Piece> = Vec::new();
+    let mut run = None;
+    let mut size: f32 = 0.;
+    for c in chars {
+        size = size.max(runs[c.run].style.size);
+        if c.c.is_control() {
+            continue;
+        }
+        if run != Some(c.run) {
+            run = Some(c.run);
+            pieces.push(Piece {
+                glyphs: Vec::new(),
+                style: runs[c.run].style.clone(),
+            });
+        }
+        let mut glyph = c.glyph.clone();
+        glyph.set_position(point(c.left() - left, 0.));
+        pieces.last_mut().unwrap().glyphs.push(glyph);
+    }
+    if chars.is_empty() {
+        size = 1.;
+    }
+
+    let range = match (chars.first(), chars.last()) {
+        (Some(first), Some(last)) => first.index..last.index + last.c.len_utf8(),
+        _ => 0..0,
+    };
+    let v_metrics = fonts[0].v_metrics(scale);
+    Line {
+        pieces,
+        range,
+        width: line_width(chars),
+        ascent: v_metrics.ascent * size,
+        height: (v_metrics.ascent - v_metrics.descent).ceil() * size,
+    }
+}
+
+/// Lay out runs in lines no wider than width, breaking them where the Unicode line breaking
+/// rules allow and wherever the text has a line break. Parts of the text too wide to fit on
+/// any line are broken between characters
+pub fn wrap<'a>(fonts: &[&Font<'a>], runs: &[Run], scale: Scale, width: f32) -> Vec<Line<'a>> {
+    let text = plain_text(runs);
+    if text.is_empty() {
+        return Vec::new();
+    }
+    let chars = place(fonts, runs, scale);
+
+    let mut lines = Vec::new();
+    // Indices in chars of the start of the current line and of the next part to fit on it
+    let (mut start, mut next) = (0, 0);
+    for (index, opportunity) in linebreaks(&text) {
+        let end = chars[next..]
+            .iter()
+            .position(|c| c.index >= index)
+            .map_or(chars.len(), |i| next + i);
+        if visible_right(&chars[start..next]).is_some() && line_width(&chars[start..end]) > width {
+            lines.push(line(fonts, runs, scale, &chars[start..next]));
+            start = next;
+        }
+        // Parts too wide for a line of their own are broken between characters
+        while start < end && line_width(&chars[start..end]) > width {
+            let fits = (start + 1..end)
+                .take_while(|&i| line_width(&chars[start..i + 1]) <= width)
+                .last()
+                .map_or(start + 1, |i| i + 1);
+            lines.push(line(fonts, runs, scale, &chars[start..fits]));
+            start = fits;
+        }
+        next = end;
+        if opportunity == BreakOpportunity::Mandatory {
+            lines.push(line(fonts, runs, scale, &chars[start..next]));
+            start = next;
+        }
+    }
+
+    lines
+}
+
+/// Width of text laid out on a single line
+pub fn measure(fonts: &[&Font<'_>], text: &str, scale: Scale) -> f32 {
+    wrap(fonts, &[Run::plain(text)], scale, f32::INFINITY)
+        .iter()
+        .map(|line| line.width)
+        .fold(0., f32::max)
+}
+
+/// Height lines take stacked one under another
+pub fn height(lines: &[Line]) -> f32 {
+    lines.iter().map(|line| line.height).sum()
+}
+
+#[cfg(test)]
+mod test {
+    use rusttype::{point, Font, Scale};
+
+    use super::{height, measure, wrap};
+    use crate::engine::markup::Run;
+
+    fn glyphs(line: &super::Line) -> usize {
+        line.pieces.iter().map(|piece| piece.glyphs.len()).sum()
+    }
+
+    #[test]
+    fn layout() {
+        let calibri =
+            Font::try_from_bytes(include_bytes!("../../resources/fonts/calibri-regular.ttf"))
+                .unwrap();
+        let fonts = [&calibri];
+        let scale = Scale::uniform(24.0);
+
+        // Kerning is kept across spaces
+        let lines = wrap(&fonts, &[Run::plain("AV AV")], scale, f32::INFINITY);
+        let expected = calibri
+            .layout("AV AV", scale, point(0., 0.))
+            .map(|glyph| glyph.position())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines[0].pieces[0]
+                .glyphs
+                .iter()
+                .map(|glyph| glyph.position())
+                .collect::<Vec<_>>(),
+            expected
+        );
+
+        // Lines wrap between words, and line breaks are kept
+        let word = measure(&fonts, "word", scale);
+        let lines = wrap(
+            &fonts,
+            &[Run::plain("word word word\n\nend")],
+            scale,
+            word * 2.5,
+        );
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines.iter().map(glyphs).collect::<Vec<_>>(),
+            ["word word ".len(), "word".len(), 0, "end".len()]
+        );
+        assert_eq!(lines[1].range, 10..15);
+        assert!(lines.iter().all(|line| line.width <= word * 2.5));
+        assert_eq!(height(&lines), lines[0].height * 4.);
+
+        // Text without spaces still wraps
+        let lines = wrap(&fonts, &[Run::plain("日本語の文章です")], scale, 60.0);
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|line| line.width <= 60.0));
+
+        let lines = wrap(&fonts, &[Run::plain("unbreakable")], scale, word);
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|line| line.width <= word));
+
+        assert!(wrap(&fonts, &[], scale, 100.0).is_empty());
+    }
+}
@@ -1,8 +1,16 @@
+pub mod assets;
 mod draw;
 pub mod error;
+#[cfg(test)]
+pub(crate) mod fixtures;
+pub mod font;
+pub mod layout;
 mod scene;
 mod size;
 
-pub use draw::{draw_text, load_image};
-pub use scene::Scene;
+pub use assets::Assets;
+#[allow(deprecated)]
+pub use draw::load_image;
+pub use draw::{decode_image, draw_text, read_image};
+pub use scene::{Overflow, Scene};
 pub use size::Size;
@@ -1,6 +1,9 @@
 use std::{
-    collections::hash_map::DefaultHasher,
+    borrow::Cow,
+    collections::{hash_map::DefaultHasher, HashMap},
     hash::{Hash, Hasher},
+    ops::Range,
+    str::FromStr,
 };
 
 use image::{
@@ -10,106 +13,359 @@ use image::{
 use imageproc::drawing::draw_filled_circle_mut;
 use rusttype::{point, Font, Scale};
 
-use crate::engine::{engine::Attributes, SpriteDirective};
-use log::{trace, warn};
+use crate::engine::{
+    frame::{Color, Frame, FrameContent},
+    markup::{slice, Run},
+    Config, FrameSprite, ParseError, ScriptLoader,
+};
 
 use super::{
-    draw::{as_glyphs, draw_rounded_rect, draw_words, glyphs_width, load_image},
+    draw::{draw_line, draw_rounded_rect},
     draw_text,
+    layout::{self, measure, wrap},
     size::Size,
 };
 
+/// Font used when `[Scene]` sets none
+pub const DEFAULT_FONT: &[u8] = include_bytes!("../../resources/fonts/calibri-regular.ttf");
+
+/// Radius of the corners of the text box and the speaker's name box
+const CORNER_RADIUS: u32 = 8;
+
+/// Smallest part of the scene's scale dialogue is shrunk down to
+const MIN_SHRINK: f32 = 0.25;
+
+/// What to do with dialogue too long to fit the text box
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    /// Shrink the text until it fits
+    Shrink,
+    /// Split the dialogue into pages shown one after another, each fitting at the scene's scale
+    Paginate,
+}
+
+impl FromStr for Overflow {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shrink" => Ok(Self::Shrink),
+            "paginate" => Ok(Self::Paginate),
+            _ => Err(()),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Scene {
     pub font: Font<'static>,
+    /// Fonts tried in order for characters the font drawing them has no glyph for
+    pub fallback: Vec<Font<'static>>,
+    /// Fonts characters may use instead of font, by name
+    pub fonts: HashMap<String, Font<'static>>,
     pub scale: Scale,
     pub screen: Size,
     pub sprite: Size,
     pub text: Size,
+    pub overflow: Overflow,
+}
+
+/// Parse key of the `[Scene]` section, or default if it is not set
+fn scene_field<T: FromStr>(
+    section: Option<&HashMap<String, String>>,
+    key: &str,
+    default: T,
+) -> Result<T, ParseError> {
+    match section.and_then(|section| section.get(key)) {
+        Some(value) => value.parse().map_err(|_| {
+            ParseError::InvalidConfig(format!("Scene.{}", key), format!("cannot parse {}", value))
+        }),
+        None => Ok(default),
+    }
+}
+
+/// Parse the `min, max` range of key of the `[Scene]` section, which must lie within 0..=limit
+fn scene_range(
+    section: Option<&HashMap<String, String>>,
+    key: &str,
+    default: (u32, u32),
+    limit: u32,
+) -> Result<(u32, u32), ParseError> {
+    let invalid = |why: String| ParseError::InvalidConfig(format!("Scene.{}", key), why);
+    let (min, max) = match section.and_then(|section| section.get(key)) {
+        Some(value) => match value
+            .split(',')
+            .map(|bound| bound.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .as_deref()
+        {
+            Ok(&[min, max]) => (min, max),
+            _ => return Err(invalid(format!("expected min, max but got {}", value))),
+        },
+        None => default,
+    };
+    if min >= max || max > limit {
+        return Err(invalid(format!(
+            "{}, {} is not a range within 0 and {}",
+            min, max, limit
+        )));
+    }
+    Ok((min, max))
+}
+
+/// Read the font at path through loader for key of the config
+fn load_font(
+    loader: &dyn ScriptLoader,
+    key: &str,
+    path: &str,
+) -> Result<Font<'static>, ParseError> {
+    let bytes = loader
+        .read(path)
+        .map_err(|e| ParseError::IoError(path.to_string(), e))?;
+    Font::try_from_vec(bytes).ok_or_else(|| {
+        ParseError::InvalidConfig(key.to_string(), format!("{} is not a font", path))
+    })
 }
 
 impl Scene {
-    pub fn dialogue_hash(
-        &self,
-        bg: Option<&DynamicImage>,
-        sprites: &[SpriteDirective],
-        character_name: &str,
-        dialogue: &str,
-        attributes: &Attributes,
-    ) -> u64 {
+    /// Build the scene described by the `[Scene]` section of config, reading its fonts and
+    /// those named in `[Fonts]` through loader. Unset keys keep the 640x480 layout with
+    /// Calibri at scale 24
+    pub fn from_config(config: &Config, loader: &dyn ScriptLoader) -> Result<Self, ParseError> {
+        let section = config.fields.get("Scene");
+        let width = scene_field(section, "width", 640)?;
+        let height = scene_field(section, "height", 480)?;
+        let (text_xmin, text_xmax) = scene_range(section, "text_x", (20, 620), width)?;
+        let (text_ymin, text_ymax) = scene_range(section, "text_y", (340, 470), height)?;
+        let scale = scene_field(section, "scale", 24.0)?;
+        let overflow = scene_field(section, "overflow", Overflow::Paginate)?;
+
+        let font = match section.and_then(|section| section.get("font")) {
+            Some(path) => load_font(loader, "Scene.font", path)?,
+            None => Font::try_from_bytes(DEFAULT_FONT).expect("Default font is malformed"),
+        };
+        let fallback = match section.and_then(|section| section.get("fallback_fonts")) {
+            Some(paths) => paths
+                .split(',')
+                .map(|path| load_font(loader, "Scene.fallback_fonts", path.trim()))
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+        let fonts = match config.fields.get("Fonts") {
+            Some(fonts) => fonts
+                .iter()
+                .map(|(name, path)| {
+                    Ok((
+                        name.clone(),
+                        load_font(loader, &format!("Fonts.{}", name), path)?,
+                    ))
+                })
+                .collect::<Result<_, ParseError>>()?,
+            None => HashMap::new(),
+        };
+
+        let scene = Self {
+            font,
+            fallback,
+            fonts,
+            scale: Scale::uniform(scale),
+            screen: Size {
+                xmin: 0,
+                xmax: width,
+                ymin: 0,
+                ymax: height,
+            },
+            sprite: Size {
+                xmin: 0,
+                xmax: 0,
+                ymin: 0,
+                ymax: 0,
+            },
+            text: Size {
+                xmin: text_xmin,
+                xmax: text_xmax,
+                ymin: text_ymin,
+                ymax: text_ymax,
+            },
+            overflow,
+        };
+        scene.check_text_box()?;
+        Ok(scene)
+    }
+
+    /// Check the text box can be drawn, with room for its rounded corners, the speaker's name
+    /// above it and a line of text at the scene's scale
+    fn check_text_box(&self) -> Result<(), ParseError> {
+        if !(self.scale.x.is_finite() && self.scale.x > 0.0) {
+            return Err(ParseError::InvalidConfig(
+                "Scene.scale".to_string(),
+                format!("{} is not a positive scale", self.scale.x),
+            ));
+        }
+        if self.text.xmax - self.text.xmin < CORNER_RADIUS * 2 {
+            return Err(ParseError::InvalidConfig(
+                "Scene.text_x".to_string(),
+                format!(
+                    "text box is narrower than its {}px corners",
+                    CORNER_RADIUS * 2
+                ),
+            ));
+        }
+
+        let v_metrics = self.font.v_metrics(self.scale);
+        let name_height = (v_metrics.ascent - v_metrics.descent) as u32 + 20;
+        let line_height = (v_metrics.ascent - v_metrics.descent).ceil();
+        let invalid = |why: String| ParseError::InvalidConfig("Scene.text_y".to_string(), why);
+        if self.text.ymin < name_height {
+            return Err(invalid(format!(
+                "text box leaves no room for the speaker's name above it at scale {}",
+                self.scale.x
+            )));
+        }
+        if self.text.ymax - self.text.ymin < CORNER_RADIUS * 2
+            || self.text_area(&[&self.font], self.scale).1 < line_height
+        {
+            return Err(invalid(format!(
+                "text box is too short for a line of text at scale {}",
+                self.scale.x
+            )));
+        }
+        Ok(())
+    }
+
+    /// Fonts to draw text with, the font named font or the scene's font followed by the
+    /// fallback fonts. Unknown font names fall back to the scene's font, the engine warning
+    /// about them when the script naming them loads
+    pub fn font_chain(&self, font: Option<&str>) -> Vec<&Font<'static>> {
+        font.and_then(|name| self.fonts.get(name))
+            .into_iter()
+            .chain(std::iter::once(&self.font))
+            .chain(&self.fallback)
+            .collect()
+    }
+
+    /// Width and height dialogue text drawn with fonts at scale may take in the text box
+    fn text_area(&self, fonts: &[&Font], scale: Scale) -> (f32, f32) {
+        let v_metrics = fonts[0].v_metrics(scale);
+        let glyphs_height = (v_metrics.ascent - v_metrics.descent).ceil();
+        let vertical_pad = self.font.v_metrics(self.scale).ascent.trunc();
+        (
+            (self.text.xmax - self.text.xmin) as f32 - measure(fonts, "_", scale),
+            (self.text.ymax - self.text.ymin) as f32 - vertical_pad - glyphs_height,
+        )
+    }
+
+    /// Split dialogue drawn in the font named font into pages, each fitting the text box at
+    /// the scene's scale. Dialogue is left on a single page if it overflows by shrinking
+    pub fn paginate(&self, dialogue: &[Run], font: Option<&str>) -> Vec<Vec<Run>> {
+        if self.overflow == Overflow::Shrink {
+            return vec![dialogue.to_vec()];
+        }
+        let fonts = self.font_chain(font);
+        let (width, height) = self.text_area(&fonts, self.scale);
+
+        // Pages take lines while they fit, but at least one
+        let mut pages: Vec<(Range<usize>, f32)> = Vec::new();
+        for line in wrap(&fonts, dialogue, self.scale, width) {
+            match pages.last_mut() {
+                Some((range, used)) if *used + line.height < height => {
+                    range.end = line.range.end;
+                    *used += line.height;
+                }
+                _ => pages.push((line.range, line.height)),
+            }
+        }
+        if pages.len() < 2 {
+            return vec![dialogue.to_vec()];
+        }
+        pages
+            .into_iter()
+            .map(|(range, _)| slice(dialogue, range))
+            .collect()
+    }
+
+    /// Hash of everything frame renders, identical frames rendering to identical images
+    pub fn frame_hash(&self, frame: &Frame) -> u64 {
         let mut hasher = DefaultHasher::default();
-        let text_color = attributes
-            .get_path(&format!("character.{}.text_color", character_name))
-            .map(|val| {
-                let c = u32::from_str_radix(val.as_value().unwrap(), 16).unwrap();
-                let a = c & 0xFF;
-                let b = (c >> 8) & 0xFF;
-                let g = (c >> 16) & 0xFF;
-                let r = (c >> 24) & 0xFF;
-                [r as u8, g as u8, b as u8, a as u8]
-            })
-            .unwrap_or([255, 255, 255, 255]);
-        let dialogue_background = attributes
-            .get_path(&format!("character.{}.dialogue_color", character_name))
-            .map(|val| {
-                let c = u32::from_str_radix(val.as_value().unwrap(), 16).unwrap();
-                let a = c & 0xFF;
-                let b = (c >> 8) & 0xFF;
-                let g = (c >> 16) & 0xFF;
-                let r = (c >> 24) & 0xFF;
-                [r as u8, g as u8, b as u8, a as u8]
-            })
-            .unwrap_or([0, 0, 0, 255 / 2]);
 
-        if let Some(bg) = bg {
+        if let Some(bg) = &frame.bg {
             bg.hash(&mut hasher);
         }
-        text_color.hash(&mut hasher);
-        dialogue_background.hash(&mut hasher);
-
-        for sprite in sprites.iter().filter(|s| s.show) {
-            sprite.hash(&mut hasher);
-            if let Some(Ok(scale)) = attributes
-                .get_path(&format!("sprite.{}.scale", sprite.name))
-                .map(|f| f.as_value().unwrap().parse::<f64>())
-            {
+        for sprite in &frame.sprites {
+            sprite.name.hash(&mut hasher);
+            sprite.path.hash(&mut hasher);
+            (sprite.x, sprite.y).hash(&mut hasher);
+            if let Some(scale) = sprite.scale {
                 ((scale * 100.) as u64).hash(&mut hasher); // approximate scale as floating points have nuances making it undesirable to be hashed
             }
-            // Priorities doesn't matter if sprites are loaded in the same order
-            // if let Some(priority) = attributes
-            //     .get_path(&format!("sprite.{}.priority", sprite.name))
-            //     .map(|v| v.as_value().unwrap())
-            // {
-            //     priority.hash(&mut hasher);
-            // }
         }
-        dialogue.hash(&mut hasher);
+        match &frame.content {
+            FrameContent::Dialogue {
+                speaker,
+                runs,
+                text_color,
+                dialogue_color,
+                font,
+                ..
+            } => {
+                font.hash(&mut hasher);
+                text_color.hash(&mut hasher);
+                dialogue_color.hash(&mut hasher);
+                speaker.hash(&mut hasher);
+                for run in runs {
+                    run.text.hash(&mut hasher);
+                    (run.style.bold, run.style.italic, run.style.color).hash(&mut hasher);
+                    run.style.size.to_bits().hash(&mut hasher);
+                }
+            }
+            FrameContent::Choice { choices } => choices.hash(&mut hasher),
+        }
 
         hasher.finish()
     }
+
+    pub fn draw_frame(&self, frame: &Frame) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let bg = frame.bg.as_deref();
+        match &frame.content {
+            FrameContent::Dialogue {
+                speaker,
+                runs,
+                text_color,
+                dialogue_color,
+                font,
+                ..
+            } => self.draw_dialogue(
+                bg,
+                &frame.sprites,
+                speaker,
+                runs,
+                font.as_deref(),
+                *text_color,
+                *dialogue_color,
+            ),
+            FrameContent::Choice { choices } => {
+                self.draw_choice(bg, &choices.iter().map(String::as_str).collect::<Vec<_>>())
+            }
+        }
+    }
+
+    /// Draw a dialogue, its speaker and text in the font named font or the scene's font
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_dialogue(
         &self,
         bg: Option<&DynamicImage>,
-        sprites: &[SpriteDirective],
+        sprites: &[FrameSprite],
         character_name: &str,
-        dialogue: &str,
-        attributes: &Attributes,
+        dialogue: &[Run],
+        font: Option<&str>,
+        text_color: Color,
+        dialogue_background: Color,
     ) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let fonts = self.font_chain(font);
         let v_metrics = self.font.v_metrics(self.scale);
         let height = v_metrics.ascent - v_metrics.descent;
         let mut image = DynamicImage::new_rgba8(self.screen.xmax, self.screen.ymax).to_rgba8();
 
-        let text_color = attributes
-            .get_path(&format!("character.{}.text_color", character_name))
-            .map(|val| {
-                let c = u32::from_str_radix(val.as_value().unwrap(), 16).unwrap();
-                let a = c & 0xFF;
-                let b = (c >> 8) & 0xFF;
-                let g = (c >> 16) & 0xFF;
-                let r = (c >> 24) & 0xFF;
-                [r as u8, g as u8, b as u8, a as u8]
-            })
-            .unwrap_or([255, 255, 255, 255]);
         let text_color = Rgba::from_slice(&text_color);
 
         let mut text_box: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(
@@ -126,58 +382,29 @@ impl Scene {
             overlay(&mut image, &resized_bg, 0, 0);
         }
 
-        for sprite in sprites.iter().filter(|s| s.show) {
-            if let Some(sprite_path) = &sprite.sprite_path {
-                let mut sprite_img = load_image(sprite_path).expect("Unable to load sprite");
-                let (mut width, mut height) = sprite_img.dimensions();
-                if let Some(scale) = attributes
-                    .get_path(&format!("sprite.{}.scale", sprite.name))
-                    .map(|f| f.as_value().unwrap().parse::<f64>())
-                {
-                    trace!("{}", "Scaling character");
-                    if let Ok(scale) = scale {
-                        sprite_img = sprite_img.resize_exact(
-                            (width as f64 * scale) as u32,
-                            (height as f64 * scale) as u32,
-                            image::imageops::FilterType::Gaussian,
-                        );
-                        (width, height) = sprite_img.dimensions();
-                    } else {
-                        warn!("{}", "scale cannot be parsed as a float. Ignoring scaling");
-                    }
-                }
-                let (x, y) = (sprite.x.unwrap() as i32, sprite.y.unwrap() as i32);
-                let left = (width as i32 / 2 - x).max(self.screen.xmin as i32);
-                let top = (height as i32 / 2 - y).max(self.screen.ymin as i32);
-
-                let mut effective_x = sprite.x.unwrap() as i32 - width as i32 / 2;
-                let mut effective_y = sprite.y.unwrap() as i32 - height as i32 / 2;
-                if left > 0 || top > 0 {
-                    sprite_img = sprite_img.crop_imm(left as u32, top as u32, width, height);
-                    effective_x += left;
-                    effective_y += top;
-                }
-
-                overlay(
-                    &mut image,
-                    &sprite_img,
-                    effective_x as u32,
-                    effective_y as u32,
-                );
+        for sprite in sprites {
+            let mut sprite_img = Cow::Borrowed(sprite.image.as_ref());
+            let (width, height) = sprite_img.dimensions();
+            let (x, y) = (sprite.x as i32, sprite.y as i32);
+            let left = (width as i32 / 2 - x).max(self.screen.xmin as i32);
+            let top = (height as i32 / 2 - y).max(self.screen.ymin as i32);
+
+            let mut effective_x = x - width as i32 / 2;
+            let mut effective_y = y - height as i32 / 2;
+            if left > 0 || top > 0 {
+                sprite_img =
+                    Cow::Owned(sprite_img.crop_imm(left as u32, top as u32, width, height));
+                effective_x += left;
+                effective_y += top;
             }
-        }
-        let dialogue_background = attributes
-            .get_path(&format!("character.{}.dialogue_color", character_name))
-            .map(|val| {
-                let c = u32::from_str_radix(val.as_value().unwrap(), 16).unwrap();
-                let a = c & 0xFF;
-                let b = (c >> 8) & 0xFF;
-                let g = (c >> 16) & 0xFF;
-                let r = (c >> 24) & 0xFF;
-                [r as u8, g as u8, b as u8, a as u8]
-            })
-            .unwrap_or([0, 0, 0, 255 / 2]);
 
+            overlay(
+                &mut image,
+                sprite_img.as_ref(),
+                effective_x as u32,
+                effective_y as u32,
+            );
+        }
         draw_rounded_rect(
             &mut text_box,
             (0, height as u32 + 20),
@@ -186,16 +413,12 @@ impl Scene {
                 self.text.ymax - self.text.ymin + height as u32 + 20,
             ),
             dialogue_background.into(),
-            8,
+            CORNER_RADIUS,
         );
 
         if !character_name.is_empty() {
-            let name_width = glyphs_width(&as_glyphs(
-                character_name,
-                &self.font,
-                self.scale,
-                point(0., 0.),
-            ));
+            let name_width =
+                (measure(&fonts, character_name, self.scale) as u32).max(CORNER_RADIUS * 2);
 
             let height = v_metrics.ascent - v_metrics.descent;
 
@@ -204,7 +427,7 @@ impl Scene {
                 (0, 0),
                 (name_width, height as u32 + 30),
                 dialogue_background.into(),
-                8,
+                CORNER_RADIUS,
             );
 
             draw_filled_circle_mut(
@@ -220,7 +443,7 @@ impl Scene {
                 character_name,
                 text_color,
                 &mut text_box,
-                &self.font,
+                &fonts,
                 self.scale,
                 point(15., height + 5.),
             );
@@ -233,58 +456,32 @@ impl Scene {
             self.text.ymin - height as u32 - 20,
         );
 
+        // Dialogue too long for the text box is shrunk, unless it was paginated to fit. Past
+        // the smallest scale it is drawn overflowing the box
+        let min_scale = self.scale.x * MIN_SHRINK;
         let mut scale = self.scale;
-        let vertical_pad = v_metrics.ascent as u32;
-
-        let ycur = self.text.ymin;
-        let mut v_metrics;
-        let mut glyphs_height;
-        let mut whitespace_width;
+        let mut lines;
         loop {
-            v_metrics = self.font.v_metrics(scale);
-            glyphs_height = (v_metrics.ascent - v_metrics.descent).ceil() as u32;
-            whitespace_width = glyphs_width(
-                &self
-                    .font
-                    .layout("_", scale, point(0., 0.))
-                    .collect::<Vec<_>>(),
-            );
-            let (y, _) = dialogue
-                .split(' ')
-                .map(|word| as_glyphs(word, &self.font, scale, point(self.text.xmin as f32, 0.)))
-                .fold(
-                    (ycur, self.text.xmin + whitespace_width),
-                    |(mut ycur, mut xcur), glyphs| {
-                        if !glyphs.is_empty() {
-                            let width = glyphs_width(&glyphs);
-                            if xcur + width + whitespace_width > self.text.xmax - self.text.xmin {
-                                ycur += glyphs_height;
-                                xcur = self.text.xmin;
-                            }
-                            xcur += width + whitespace_width;
-                        }
-
-                        (ycur, xcur)
-                    },
-                );
-            if y + vertical_pad + glyphs_height * 2 < self.text.ymax {
+            let (width, height) = self.text_area(&fonts, scale);
+            lines = wrap(&fonts, dialogue, scale, width);
+            if layout::height(&lines) < height || scale.x <= min_scale {
                 break;
             }
-            scale = Scale::uniform(scale.x * 0.95);
+            scale = Scale::uniform((scale.x * 0.95).max(min_scale));
         }
 
-        draw_words(
-            dialogue,
-            text_color,
-            &mut image,
-            &self.font,
-            scale,
-            point(
-                whitespace_width as f32 + self.text.xmin as f32,
-                whitespace_width as f32 + self.text.ymin as f32 + glyphs_height as f32,
-            ),
-            self.text.xmax - self.text.xmin - whitespace_width,
-        );
+        // Lines are as tall as their largest run
+        let v_metrics = fonts[0].v_metrics(scale);
+        let whitespace_width = measure(&fonts, "_", scale);
+        let x = whitespace_width + self.text.xmin as f32;
+        let mut top = whitespace_width
+            + self.text.ymin as f32
+            + (v_metrics.ascent - v_metrics.descent).ceil()
+            - v_metrics.ascent;
+        for line in &lines {
+            draw_line(&mut image, line, text_color, x, top + line.ascent);
+            top += line.height;
+        }
 
         image
     }
@@ -292,20 +489,14 @@ impl Scene {
     pub fn draw_choice(
         &self,
         bg: Option<&DynamicImage>,
-        choices: &(&str, &str),
+        choices: &[&str],
     ) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let fonts = self.font_chain(None);
         let v_metrics = self.font.v_metrics(self.scale);
         let glyph_height = v_metrics.ascent - v_metrics.descent;
         let mut image = DynamicImage::new_rgba8(self.screen.xmax, self.screen.ymax).to_rgba8();
         let white = Rgba::from_slice(&[255, 255, 255, 255]);
 
-        let mut opacity_box: ImageBuffer<Rgba<u8>, Vec<u8>> =
-            ImageBuffer::new(self.screen.xmax, (glyph_height * 1.5) as u32);
-
-        for pixel in opacity_box.pixels_mut() {
-            pixel.0 = [0, 0, 0, 255 / 2];
-        }
-
         if let Some(bg) = bg {
             let resized_bg = bg.resize_exact(
                 self.screen.xmax - self.screen.xmin,
@@ -315,61 +506,113 @@ impl Scene {
             overlay(&mut image, &resized_bg, 0, 0);
         }
 
-        let a_glyphs = &as_glyphs(
-            choices.0,
-            &self.font,
-            self.scale,
-            point(self.screen.xmax as f32 / 2.0, self.screen.ymax as f32 / 4.0),
-        );
-        let a_width = glyphs_width(a_glyphs);
-        let b_glyphs = &as_glyphs(
-            choices.1,
-            &self.font,
-            self.scale,
-            point(
-                self.screen.xmax as f32 / 2.0,
-                self.screen.ymax as f32 / 4.0 + v_metrics.ascent * 5.0,
-            ),
-        );
-        let b_width = glyphs_width(b_glyphs);
+        // Choices are spread evenly below the top quarter of the screen, squeezing them
+        // together if they wouldn't otherwise fit
+        let top = self.screen.ymax as f32 / 4.0;
+        let bottom = self.screen.ymax as f32 - glyph_height * 2.0;
+        let spacing = if choices.len() > 1 {
+            (v_metrics.ascent * 5.0).min((bottom - top) / (choices.len() - 1) as f32)
+        } else {
+            0.0
+        };
+
+        // Choices too wide for the screen wrap onto centered lines
+        let width = (self.screen.xmax - self.screen.xmin) as f32 - glyph_height * 2.0;
+        for (i, choice) in choices.iter().enumerate() {
+            let y = top + spacing * i as f32;
+            let lines = wrap(&fonts, &[Run::plain(choice)], self.scale, width);
+
+            let opacity_box = ImageBuffer::from_pixel(
+                self.screen.xmax,
+                (glyph_height * 0.5 + layout::height(&lines)) as u32,
+                Rgba([0, 0, 0, 255 / 2]),
+            );
+            overlay(&mut image, &opacity_box, 0, (y - glyph_height) as u32);
 
-        overlay(
-            &mut image,
-            &opacity_box,
-            0,
-            (self.screen.ymax as f32 / 4.0 - glyph_height) as u32,
-        );
+            let mut baseline = y;
+            for line in &lines {
+                let x = self.screen.xmax as f32 / 2.0 - line.width / 2.0;
+                draw_line(&mut image, line, white, x, baseline);
+                baseline += line.height;
+            }
+        }
 
-        draw_text(
-            choices.0,
-            white,
-            &mut image,
-            &self.font,
-            self.scale,
-            point(
-                self.screen.xmax as f32 / 2.0 - a_width as f32 / 2.0,
-                self.screen.ymax as f32 / 4.0,
-            ),
-        );
-        overlay(
-            &mut image,
-            &opacity_box,
-            0,
-            (self.screen.ymax as f32 / 4.0 + v_metrics.ascent * 5.0 - glyph_height) as u32,
-        );
+        image
+    }
+}
 
-        draw_text(
-            choices.1,
-            white,
-            &mut image,
-            &self.font,
-            self.scale,
-            point(
-                self.screen.xmax as f32 / 2.0 - b_width as f32 / 2.0,
-                self.screen.ymax as f32 / 4.0 + v_metrics.ascent * 5.0,
-            ),
+#[cfg(test)]
+mod test {
+    use crate::engine::{markup::Run, Config, MemoryLoader, ParseError};
+
+    use super::{Overflow, Scene};
+
+    #[test]
+    fn from_config() {
+        let mut loader = MemoryLoader::new();
+        loader.insert_file(
+            "fonts/cour.ttf",
+            include_bytes!("../../resources/fonts/cour.ttf").to_vec(),
         );
+        let config = Config::parse(
+            "config.conf",
+            "[Scene]\nwidth = 800\nheight = 600\ntext_y = 400, 590\nfont = fonts/cour.ttf\nscale = 30\n\
+             fallback_fonts = fonts/cour.ttf, fonts/cour.ttf\n[Fonts]\nrobot = fonts/cour.ttf\n",
+        )
+        .unwrap();
+        let scene = Scene::from_config(&config, &loader).unwrap();
+        assert_eq!((scene.screen.xmax, scene.screen.ymax), (800, 600));
+        assert_eq!((scene.text.xmin, scene.text.xmax), (20, 620));
+        assert_eq!((scene.text.ymin, scene.text.ymax), (400, 590));
+        assert_eq!(scene.scale.x, 30.0);
+        assert_eq!(scene.font_chain(None).len(), 3);
+        assert_eq!(scene.font_chain(Some("robot")).len(), 4);
+        assert_eq!(scene.font_chain(Some("none")).len(), 3);
+        assert_eq!(scene.overflow, Overflow::Paginate);
+
+        let defaults = Scene::from_config(&Config::parse("config.conf", "").unwrap(), &loader);
+        assert_eq!(defaults.unwrap().screen.xmax, 640);
+
+        for scene in [
+            "text_x = 20, 700",
+            "text_y = 300",
+            "text_y = 340, 357",
+            "text_y = 340, 350",
+            "text_y = 10, 200",
+            "text_x = 20, 30",
+            "scale = 0",
+            "width = wide",
+            "overflow = scroll",
+            "font = none.ttf",
+            "fallback_fonts = fonts/cour.ttf, none.ttf",
+        ] {
+            let config = Config::parse("config.conf", &format!("[Scene]\n{}\n", scene)).unwrap();
+            assert!(
+                matches!(
+                    Scene::from_config(&config, &loader),
+                    Err(ParseError::InvalidConfig(..) | ParseError::IoError(..))
+                ),
+                "{}",
+                scene
+            );
+        }
+    }
 
-        image
+    #[test]
+    fn shrink_floor() {
+        let config = Config::parse("config.conf", "[Scene]\noverflow = shrink\n").unwrap();
+        let scene = Scene::from_config(&config, &MemoryLoader::new()).unwrap();
+        // Too long to fit at any scale, so it is drawn at the smallest one
+        let dialogue = "word ".repeat(3000);
+        let image = scene.draw_dialogue(
+            None,
+            &[],
+            "I",
+            &[Run::plain(&dialogue)],
+            None,
+            [255, 255, 255, 255],
+            [0, 0, 0, 255],
+        );
+        assert_eq!(image.dimensions(), (640, 480));
     }
 }
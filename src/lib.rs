@@ -4,4 +4,4 @@ pub mod img;
 
 pub use discord::Handler;
 pub use engine::{Config, Engine, Script, SpriteDirective};
-pub use img::{Scene, Size};
+pub use img::{Overflow, Scene, Size};